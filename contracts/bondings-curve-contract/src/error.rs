@@ -1,9 +1,11 @@
 use ckb_std::error::SysError;
+use ckbfi_common::{error::BONDINGS_CURVE, pool::PoolError, registry::RegistryError, token::TokenError};
+use ckbfi_curve::CurveError;
 
-/// Error
+/// Error, numbered from the start of the contract's range in ckbfi_common::error
 #[repr(i8)]
 pub enum Error {
-    IndexOutOfBound = 1,
+    IndexOutOfBound = BONDINGS_CURVE.base,
     ItemMissing,
     LengthNotEnough,
     Encoding,
@@ -20,8 +22,29 @@ pub enum Error {
     PermissionDenied,
     // 输入需要包含pool xudt
     InputValidationFailure,
+    // 不支持的代币标准
+    UnknownTokenStandard,
+    // 代币args不符合所选代币标准
+    InvalidTokenArgs,
+    // 代币data不符合所选代币标准
+    InvalidTokenData,
+    // 价格计算超出曲线范围
+    PriceOutOfRange,
+    // buy时给出的xudt少于支付ckb按曲线反算的数量
+    UserReceiveXudtNotEnough,
+    // unique cell data格式错误
+    InvalidPoolData,
+    // 池子已暂停或已紧急提取
+    PoolNotActive,
+    // registry cell数据格式错误
+    InvalidRegistry,
+    // 紧急提取时cell_deps中没有registry cell
+    RegistryMissing,
 }
 
+// the names in ckbfi_common::error decode the exit codes
+const _: () = assert!(Error::RegistryMissing as i8 + 1 == BONDINGS_CURVE.next());
+
 impl From<SysError> for Error {
     fn from(err: SysError) -> Self {
         use SysError::*;
@@ -39,3 +62,35 @@ impl From<SysError> for Error {
         }
     }
 }
+
+impl From<TokenError> for Error {
+    fn from(err: TokenError) -> Self {
+        match err {
+            TokenError::UnknownStandard => Self::UnknownTokenStandard,
+            TokenError::InvalidArgs => Self::InvalidTokenArgs,
+            TokenError::InvalidData => Self::InvalidTokenData,
+        }
+    }
+}
+
+impl From<CurveError> for Error {
+    fn from(_: CurveError) -> Self {
+        Self::PriceOutOfRange
+    }
+}
+
+impl From<PoolError> for Error {
+    fn from(err: PoolError) -> Self {
+        match err {
+            PoolError::InvalidData => Self::InvalidPoolData,
+        }
+    }
+}
+
+impl From<RegistryError> for Error {
+    fn from(err: RegistryError) -> Self {
+        match err {
+            RegistryError::InvalidData => Self::InvalidRegistry,
+        }
+    }
+}
@@ -9,29 +9,17 @@ use core::u128;
 // https://docs.rs/ckb-std/
 use ckb_std::{
     ckb_constants::Source,
-    ckb_types::{bytes::Bytes, packed::Byte32, prelude::*},
-    high_level::{load_cell, load_cell_data, load_cell_lock_hash, load_script,QueryIter},
+    ckb_types::{bytes::Bytes, packed::Script, prelude::*},
+    high_level::{load_cell, load_cell_capacity, load_cell_data, load_cell_lock, load_cell_lock_hash, load_cell_type_hash, load_script,QueryIter},
+};
+use ckbfi_common::{
+    token::{Token, TOKEN_LEN},
+    trace,
+    trace::Hex,
 };
 
 use crate::error::Error;
 
-// testnet "25c29dc317811a6f6f3985a7a9ebc4838bd388d19d0feeecf0bcd60f6c0975bb" to Byte32
-fn xudt_code_hash() -> Byte32 {
-    Byte32::from_slice(&[
-        0x25, 0xc2, 0x9d, 0xc3, 0x17, 0x81, 0x1a, 0x6f, 0x6f, 0x39, 0x85, 0xa7, 0xa9, 0xeb, 0xc4, 0x83, 0x8b, 0xd3, 0x88, 0xd1, 0x9d, 0x0f, 0xee, 0xec, 0xf0, 0xbc, 0xd6, 0x0f, 0x6c, 0x09, 0x75, 0xbb,
-    ]).expect("constant initialization")
-}
-// mainnet 0x50bd8d6680b8b9cf98b73f3c08faf8b2a21914311954118ad6609be6e78a1b95
-// fn xudt_code_hash() -> Byte32 {
-//     Byte32::from_slice(&[
-//         0x50, 0xbd, 0x8d, 0x66, 0x80, 0xb8, 0xb9, 0xcf, 0x98, 0xb7, 0x3f, 0x3c, 0x08, 0xfa, 0xf8, 0xb2,
-//         0xa2, 0x19, 0x14, 0x31, 0x19, 0x54, 0x11, 0x8a, 0xd6, 0x60, 0x9b, 0xe6, 0xe7, 0x8a, 0x1b, 0x95,
-//     ]).expect("constant initialization")
-// }
-
-
-
-
 // fn ckb_args() -> Bytes {
 //     let mut buf = [0u8; 32];
 //     buf.copy_from_slice(&[0u8; 32]);
@@ -39,17 +27,26 @@ fn xudt_code_hash() -> Byte32 {
 // }
 
 
-const UDT_LEN: usize = 16;
+const LOCK_HASH_LEN: usize = 32;
+const SLIP_POINT_LEN: usize = 2;
+const DESIRED_AMOUNT_LEN: usize = 16;
+const CKB_IN_LEN: usize = 8;
+
 
 
 
 
+// (bondings_curve_lock_hash, user_lock_hash, token, slip_point, desired_amount, ckb_in)
+type OrderArgs = (Bytes, Bytes, Token, u16, u128, Option<u64>);
 
-fn parse_args(args: &Bytes) -> Result<(Bytes,Bytes, Bytes, u16, u128), Error> {
-    //debug!("argslens: {}", args.len());
-    if args.len() != 32 + 32 + 32 + 2 + 16 {
+// args: bondings_curve_lock_hash(32) | user_lock_hash(32) | token(standard | token type hash) | slip_point(2) | desired_amount(16) | ckb_in(8, optional)
+// 带ckb_in时为exact-in买单: 用户最多花费ckb_in, desired_amount按slip_point计算最少收到的xudt
+fn parse_args(args: &Bytes) -> Result<OrderArgs, Error> {
+    let base_len = LOCK_HASH_LEN * 2 + TOKEN_LEN + SLIP_POINT_LEN + DESIRED_AMOUNT_LEN;
+    if args.len() != base_len && args.len() != base_len + CKB_IN_LEN {
         return Err(Error::InvalidArgs);
     }
+    let token_end = LOCK_HASH_LEN * 2 + TOKEN_LEN;
 
     let bondings_curve_lock_hash = {
         let mut array = [0u8; 32];
@@ -62,71 +59,94 @@ fn parse_args(args: &Bytes) -> Result<(Bytes,Bytes, Bytes, u16, u128), Error> {
         array.copy_from_slice(&args[32..64]);
         Bytes::from(array.to_vec())
     };
-    //debug!("user_pubkey: {}", hex_string(user_pubkey.as_ref()));
 
-    let xudt_args = {
-        let mut array = [0u8; 32];
-        array.copy_from_slice(&args[64..96]);
-        Bytes::from(array.to_vec())
-    };
-    //debug!("xudt_args: {}", hex_string(xudt_args.as_ref()));
+    let token = Token::parse(&args[64..token_end])?;
 
     let slip_point = {
         let mut array = [0u8; 2];
-        array.copy_from_slice(&args[96..98]);
+        array.copy_from_slice(&args[token_end..token_end + SLIP_POINT_LEN]);
         u16::from_be_bytes(array)
     };
-    //debug!("slip_point: {}", slip_point);
 
     let desired_amount = {
         let mut array = [0u8; 16];
-        array.copy_from_slice(&args[98..114]);
+        array.copy_from_slice(&args[token_end + SLIP_POINT_LEN..base_len]);
         u128::from_be_bytes(array)
     };
-    //debug!("desired_amount: {}", desired_amount);
 
-    Ok((bondings_curve_lock_hash,user_lock_hash, xudt_args, slip_point, desired_amount))
+    let ckb_in = if args.len() == base_len {
+        None
+    } else {
+        let mut array = [0u8; 8];
+        array.copy_from_slice(&args[base_len..]);
+        Some(u64::from_be_bytes(array))
+    };
+
+    Ok((bondings_curve_lock_hash,user_lock_hash, token, slip_point, desired_amount, ckb_in))
 }
 
 // collect xudt amount for user
-fn collect_xudt_amount_for_user(xudt_args: &Bytes,user_lock_hash :&Bytes) -> Result<u128, Error> {
-    let mut buf = [0u8; UDT_LEN];
+fn collect_xudt_amount_for_user(token: &Token,user_lock_hash :&Bytes) -> Result<u128, Error> {
     let mut total_amount = 0u128;
-    
-    //debug!("ckb_args: {}", hex_string(ckb_args().as_ref()));
-    //debug!("ckb_flag: {}", ckb_flag);
-    
-    //debug!("load_cell_count(Source::Output): {}", QueryIter::new(load_cell_data, Source::Output).count());
-    for (i, data) in QueryIter::new(load_cell_data, Source::Output).enumerate() {
-        // ////debug!("{} data: {:?}", source,data);
-        let cell = load_cell(i, Source::Output)?;
-        let cell_lock_hash = load_cell_lock_hash(i, Source::Output)?;
-        
-        //debug!("output cell:{}",cell);
+
+    for (i, cell_lock_hash) in QueryIter::new(load_cell_lock_hash, Source::Output).enumerate() {
         if  user_lock_hash[..] != cell_lock_hash[..] {
-            // ////debug!("cell_lock_hash: {}, script.code_hash(): {}", cell_lock_hash, script.code_hash());
-            // ////debug!("args: {}, cell.lock().args().raw_data(): {}", hex_string(args.as_ref()), hex_string(cell.lock().args().raw_data().as_ref()));
             continue;
         }
-        let cell_type_hash_opt = cell.type_().to_opt();
-        // 统计给予用户的xudt amount
-        if  data.len() == UDT_LEN && cell_type_hash_opt.is_some() {
-            
-            buf.copy_from_slice(&data);
-            let amount = u128::from_le_bytes(buf);
-            let type_scrpt = cell_type_hash_opt.unwrap();
-            if type_scrpt.code_hash() == xudt_code_hash() &&  xudt_args[..] == type_scrpt.args().raw_data()[..]  {
-                
-                total_amount += amount;
-               
-            }
-            
-            
+        // 统计给予用户的xudt amount, 通过完整的type script hash识别代币
+        if token.is_token(load_cell_type_hash(i, Source::Output)?) {
+            // amount在前16字节, 之后的数据格式由代币标准决定
+            let data = load_cell_data(i, Source::Output)?;
+            total_amount += token.parse_amount(&data)?;
         }
     }
     Ok(total_amount)
 }
 
+// 用户的output无法归属到某个订单, 所以同一用户在一个交易里的订单必须args相同(同一个script group),
+// 否则每个group都会把用户的全部output算作自己的成交
+fn check_single_order_group(script: &Script, user_lock_hash: &Bytes) -> Result<(), Error> {
+    let args = script.args().raw_data();
+    for lock in QueryIter::new(load_cell_lock, Source::Input) {
+        if lock.code_hash().as_slice() != script.code_hash().as_slice()
+            || lock.hash_type().as_slice() != script.hash_type().as_slice()
+        {
+            continue;
+        }
+        let other_args = lock.args().raw_data();
+        if other_args != args
+            && other_args.len() >= LOCK_HASH_LEN * 2
+            && other_args[LOCK_HASH_LEN..LOCK_HASH_LEN * 2] == user_lock_hash[..]
+        {
+            return Err(Error::MultipleOrderGroups);
+        }
+    }
+    Ok(())
+}
+
+// exact-in买单: 除ckb_in外, order cell的capacity都要退回给用户(包括用户xudt cell占用的capacity)
+fn check_ckb_spent(ckb_in: u64, user_lock_hash: &Bytes) -> Result<(), Error> {
+    let mut order_capacity = 0u64;
+    let mut order_count = 0u64;
+    for capacity in QueryIter::new(load_cell_capacity, Source::GroupInput) {
+        order_capacity += capacity;
+        order_count += 1;
+    }
+    let mut user_capacity = 0u64;
+    for (i, cell_lock_hash) in QueryIter::new(load_cell_lock_hash, Source::Output).enumerate() {
+        if user_lock_hash[..] == cell_lock_hash[..] {
+            user_capacity += load_cell_capacity(i, Source::Output)?;
+        }
+    }
+    trace!("ckb spent", ckb_in = ckb_in, orders = order_count, order_ckb = order_capacity, user_ckb = user_capacity);
+    // 相同args的order cell在同一个script group里, 每个都可以花费ckb_in
+    let spendable = ckb_in.checked_mul(order_count).ok_or(Error::InvalidArgs)?;
+    if user_capacity.saturating_add(spendable) < order_capacity {
+        return Err(Error::UserPayCkbTooMuch);
+    }
+    Ok(())
+}
+
 // check user cell and bondings curve cell present
 fn check_cells_present(bondings_curve_lock_hash: &Bytes,user_lock_hash: &Bytes) -> Result<(bool,bool), Error> {
     let mut is_user_lock = false;
@@ -146,35 +166,48 @@ fn check_cells_present(bondings_curve_lock_hash: &Bytes,user_lock_hash: &Bytes)
 
 pub fn main() -> Result<(), Error> {
     let script = load_script()?;
-    //debug!("order script: {}", script);
     let args: Bytes = script.args().unpack();
     
-    let (bondings_curve_lock_hash,user_lock_hash, xudt_args, slip_point, desired_amount) = parse_args(&args)?;
-    //debug!("user_pubkey: {}, xudt_args: {}, slip_point: {}, desired_amount: {}", hex_string(user_lock_hash.as_ref()), hex_string(xudt_args.as_ref()), slip_point, desired_amount);
+    let (bondings_curve_lock_hash,user_lock_hash, token, slip_point, desired_amount, ckb_in) = parse_args(&args)?;
+    trace!(
+        "args",
+        bondings_curve_lock_hash = Hex(&bondings_curve_lock_hash),
+        user_lock_hash = Hex(&user_lock_hash),
+        token = token,
+        slip_point = slip_point,
+        desired_amount = desired_amount,
+        ckb_in = ckb_in,
+    );
 
     let desired_amount_after_slip = desired_amount - desired_amount * u128::from(slip_point) / 10000;
-    //debug!("desired_amount: {}, desired_amount_after_slip: {}", desired_amount, desired_amount_after_slip);
     
     let (is_user_lock,is_bondings_lock) = check_cells_present(&bondings_curve_lock_hash,&user_lock_hash)?;
     
     if is_user_lock  {
-        //debug!("is user lock");
+        trace!("cancel");
         return Ok(());
     }
     
     if !is_bondings_lock {
-        //debug!("is not bondings lock");
         return Err(Error::MissMatchBondingsCell);
     }
     
-    let output_xudt_amount_to_user = collect_xudt_amount_for_user(&xudt_args,&user_lock_hash)?;
-    //debug!("xudt_args:{} output_amount_to_user: {}", hex_string(xudt_args.as_ref()), output_xudt_amount_to_user);
+    check_single_order_group(&script, &user_lock_hash)?;
+
+    // 相同args的order cell在同一个script group里, 每个都要收到desired_amount
+    let order_count = QueryIter::new(load_cell_capacity, Source::GroupInput).count() as u128;
+    let min_xudt_amount = desired_amount_after_slip.checked_mul(order_count).ok_or(Error::InvalidArgs)?;
+    let output_xudt_amount_to_user = collect_xudt_amount_for_user(&token,&user_lock_hash)?;
+    trace!("fill", orders = order_count, user_xudt = output_xudt_amount_to_user, min_xudt = min_xudt_amount);
     // return Err(Error::LengthNotEnough);
     
-    if output_xudt_amount_to_user < desired_amount_after_slip {
-        //debug!("output_xudt_amount_to_user < desired_amount_after_slip");
+    if output_xudt_amount_to_user < min_xudt_amount {
         return Err(Error::OutputInvalid);
     }
+
+    if let Some(ckb_in) = ckb_in {
+        check_ckb_spent(ckb_in, &user_lock_hash)?;
+    }
     return Ok(());
     
 }
@@ -1,17 +1,26 @@
 use ckb_std::error::SysError;
+use ckbfi_common::{error::ORDER, token::TokenError};
 
-/// Error
+/// Error, numbered from the start of the contract's range in ckbfi_common::error
 #[repr(i8)]
 pub enum Error {
-    IndexOutOfBound = 1,
+    IndexOutOfBound = ORDER.base,
     ItemMissing,
     LengthNotEnough,
     OutputInvalid,
     Encoding,
     MissMatchBondingsCell,
-    InvalidArgs
+    InvalidArgs,
+    UnknownTokenStandard,
+    InvalidTokenArgs,
+    InvalidTokenData,
+    UserPayCkbTooMuch,
+    MultipleOrderGroups,
 }
 
+// the names in ckbfi_common::error decode the exit codes
+const _: () = assert!(Error::MultipleOrderGroups as i8 + 1 == ORDER.next());
+
 impl From<SysError> for Error {
     fn from(err: SysError) -> Self {
         use SysError::*;
@@ -26,3 +35,13 @@ impl From<SysError> for Error {
     }
 }
 
+
+impl From<TokenError> for Error {
+    fn from(err: TokenError) -> Self {
+        match err {
+            TokenError::UnknownStandard => Self::UnknownTokenStandard,
+            TokenError::InvalidArgs => Self::InvalidTokenArgs,
+            TokenError::InvalidData => Self::InvalidTokenData,
+        }
+    }
+}
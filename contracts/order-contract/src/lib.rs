@@ -0,0 +1,16 @@
+// The contract as a library for its native simulator build in
+// native-simulators/, RISC-V builds only use main.rs.
+#![cfg_attr(not(feature = "library"), no_std)]
+
+#[cfg(feature = "library")]
+mod entry;
+#[cfg(feature = "library")]
+mod error;
+
+#[cfg(feature = "library")]
+pub fn program_entry() -> i8 {
+    match entry::main() {
+        Ok(_) => 0,
+        Err(err) => err as i8,
+    }
+}
@@ -9,81 +9,50 @@ use core::u128;
 // https://docs.rs/ckb-std/
 use ckb_std::{
     ckb_constants::Source, 
-    ckb_types::{bytes::Bytes, packed::Byte32, prelude::*},
+    ckb_types::{bytes::Bytes, packed::Script, prelude::*},
     error::SysError, 
-    high_level::{load_cell, load_cell_data, load_cell_capacity,load_cell_type_hash, load_input, load_script, load_script_hash, QueryIter}
+    high_level::{load_cell, load_cell_data, load_cell_capacity, load_cell_lock_hash, load_cell_type, load_cell_type_hash, load_input, load_input_since, load_script, load_script_hash, QueryIter},
+    since::{LockValue, Since},
 };
 use ckb_hash::new_blake2b;
+use ckbfi_common::{
+    metadata::{Metadata, METADATA_FLAG},
+    pool::{Governance, PoolData, PoolStatus, Proposal, GOVERNANCE_DELAY, PROPOSAL_FLAG},
+    registry::{Registry, REGISTRY_TYPE_HASH},
+    script_ref::{ScriptRef, TYPE_ID_REF},
+    token::{Token, TokenError, TokenStandard, TOKEN_LEN},
+    trace,
+    trace::Hex,
+    xudt::{self, OWNER_MODE_INPUT_LOCK_NOT, OWNER_MODE_INPUT_TYPE},
+};
+use ckbfi_curve::{creator_buy_cost, TOTAL_XUDT_SUPPLY, XUDT_UNIT};
 
 use crate::error::Error;
 
 
-// mainnet 0x50bd8d6680b8b9cf98b73f3c08faf8b2a21914311954118ad6609be6e78a1b95
-// fn xudt_code_hash() -> Byte32 {
-//     Byte32::from_slice(&[
-//         0x50, 0xbd, 0x8d, 0x66, 0x80, 0xb8, 0xb9, 0xcf, 0x98, 0xb7, 0x3f, 0x3c, 0x08, 0xfa, 0xf8, 0xb2,
-//         0xa2, 0x19, 0x14, 0x31, 0x19, 0x54, 0x11, 0x8a, 0xd6, 0x60, 0x9b, 0xe6, 0xe7, 0x8a, 0x1b, 0x95,
-//     ]).expect("constant initialization")
-// }
-
-// testnet 25c29dc317811a6f6f3985a7a9ebc4838bd388d19d0feeecf0bcd60f6c0975bb" to Byte32
-fn xudt_code_hash() -> Byte32 {
-    Byte32::from_slice(&[
-        0x25, 0xc2, 0x9d, 0xc3, 0x17, 0x81, 0x1a, 0x6f, 0x6f, 0x39, 0x85, 0xa7, 0xa9, 0xeb, 0xc4, 0x83, 0x8b, 0xd3, 0x88, 0xd1, 0x9d, 0x0f, 0xee, 0xec, 0xf0, 0xbc, 0xd6, 0x0f, 0x6c, 0x09, 0x75, 0xbb,
-    ]).expect("constant initialization")
-}
-
-// mainnet 0xdf00d4dd710944886c0d84d79c7a3de3940c32b7d0dad464b2052eb0ba6e4914
-// fn bondings_curve_code_hash() -> Byte32 {
-//     Byte32::from_slice(&[
-//         0xdf, 0x00, 0xd4, 0xdd, 0x71, 0x09, 0x44, 0x88, 0x6c, 0x0d, 0x84, 0xd7, 0x9c, 0x7a, 0x3d, 0xe3,
-//         0x94, 0x0c, 0x32, 0xb7, 0xd0, 0xda, 0xd4, 0x64, 0xb2, 0x05, 0x2e, 0xb0, 0xba, 0x6e, 0x49, 0x14,
-//     ]).expect("constant initialization")
-// }
-
-// testnet 0xa161a8cb20ba6b79e86f297d5c5c8a44681a521fe08bf352ab5c9401a8a66606 
-fn bondings_curve_code_hash() -> Byte32 {
-    Byte32::from_slice(&[
-        0xa1, 0x61, 0xa8, 0xcb, 0x20, 0xba, 0x6b, 0x79, 0xe8, 0x6f, 0x29, 0x7d, 0x5c, 0x5c, 0x8a, 0x44, 0x68, 0x1a, 0x52, 0x1f, 0xe0, 0x8b, 0xf3, 0x52, 0xab, 0x5c, 0x94, 0x01, 0xa8, 0xa6, 0x66, 0x06,
-    ]).expect("constant initialization")
-}
-
-
-
-fn ckb_args() -> Bytes {
-    let mut buf = [0u8; 32];
-    buf.copy_from_slice(&[0u8; 32]);
-    Bytes::from(buf.to_vec())
+// bondings curve cell的lock args: token | unique cell type hash
+fn pool_args(script: &Script) -> Result<Bytes, Error> {
+    let mut args = script.args().raw_data()[..TOKEN_LEN].to_vec();
+    args.extend_from_slice(&load_script_hash()?);
+    Ok(Bytes::from(args))
 }
 
 
-const UDT_LEN: usize = 16;
-
-
+const TYPE_ID_LEN: usize = 32;
 
 
-fn load_id_from_args(offset: usize) -> Result<[u8; 32], Error> {
-    let script = load_script()?;
-    let args = script.as_reader().args();
-    let args_data = args.raw_data();
-
-    args_data
-        .get(offset..offset + 32)
-        .ok_or(Error::InvalidArgs)?
-        .try_into()
-        .map_err(|_| Error::InvalidArgs)
-}
 
-fn load_xudt_args_from_args(offset: usize) -> Result<[u8; 32], Error> {
-    let script = load_script()?;
-    let args = script.as_reader().args();
-    let args_data = args.raw_data();
 
-    args_data
-        .get(offset..offset + 32)
-        .ok_or(Error::InvalidArgs)?
-        .try_into()
-        .map_err(|_| Error::InvalidArgs)
+// args: token(standard | token type hash) | type_id | flag(PROPOSAL_FLAG or METADATA_FLAG, proposal and metadata cell only)
+fn parse_args(args: &[u8]) -> Result<(Token, [u8; 32], Option<u8>), Error> {
+    let flag = match args.len() {
+        len if len == TOKEN_LEN + TYPE_ID_LEN => None,
+        len if len == TOKEN_LEN + TYPE_ID_LEN + 1 && (args[len - 1] == PROPOSAL_FLAG || args[len - 1] == METADATA_FLAG) => Some(args[len - 1]),
+        _ => return Err(Error::InvalidArgs),
+    };
+    let token = Token::parse(&args[..TOKEN_LEN])?;
+    let type_id = args[TOKEN_LEN..TOKEN_LEN + TYPE_ID_LEN].try_into().map_err(|_| Error::InvalidArgs)?;
+    Ok((token, type_id, flag))
 }
 
 fn is_cell_present(index: usize, source: Source) -> bool {
@@ -131,62 +100,40 @@ pub fn validate_type_id(type_id: [u8; 32]) -> Result<(), Error> {
 }
 
 // Collect all  UDT tokens and cell count
-fn collect_bondings_curve_xudt_amount(type_id: &Bytes,xudt_args: &Bytes,source:Source) -> Result<u128, Error> {
-    let mut buf = [0u8; UDT_LEN];
+fn collect_bondings_curve_xudt_amount(curve_ref: &ScriptRef,pool_args: &Bytes,token: &Token,source:Source) -> Result<u128, Error> {
     let mut total_amount = 0u128;
     
 
-    for (i, data) in QueryIter::new(load_cell_data, source).enumerate() {
-        // //debug!("{} data: {:?}", source,data);
-        if data.len() == UDT_LEN {
-            buf.copy_from_slice(&data);
-            let amount = u128::from_le_bytes(buf);
-            let cell = load_cell(i, source)?;
-            let lock_code_hash = cell.lock().code_hash();
-            // args：xudt_args(32) | type id(32)
-            if lock_code_hash != bondings_curve_code_hash() || type_id[..] != cell.lock().args().raw_data()[32..] {
-                // //debug!("cell_lock_hash: {}, script.code_hash(): {}", cell_lock_hash, script.code_hash());
-                // //debug!("args: {}, cell.lock().args().raw_data(): {}", hex_string(args.as_ref()), hex_string(cell.lock().args().raw_data().as_ref()));
-                continue;
-            }
-            let cell_type_hash_opt = cell.type_().to_opt();
-            if cell_type_hash_opt.is_none() {
-                // //debug!("cell_type_hash_opt is none");
-                continue;
-            }
-            let type_scrpt = cell_type_hash_opt.unwrap();
-            
-            if type_scrpt.code_hash() == xudt_code_hash() &&  xudt_args[..] == type_scrpt.args().raw_data()[..] {
-                total_amount += amount;
-                // cell_count += 1;
-            }
-            // //debug!("{} amount: {}", source, amount);
-            // //debug!("args: {}, type_scrpt.args().raw_data(): {}", hex_string(args.as_ref()), hex_string(type_scrpt.args().raw_data().as_ref()));
+    for (i, cell) in QueryIter::new(load_cell, source).enumerate() {
+        // args：token | unique cell type hash(32), 两部分都要一致
+        if ScriptRef::of(&cell.lock()) != *curve_ref || cell.lock().args().raw_data() != pool_args {
+            continue;
+        }
+        // 通过完整的type script hash识别代币
+        if token.is_token(load_cell_type_hash(i, source)?) {
+            // amount在前16字节, 之后的数据格式由代币标准决定
+            let data = load_cell_data(i, source)?;
+            total_amount += token.parse_amount(&data)?;
+            // cell_count += 1;
         }
     }
     Ok(total_amount)
 }
 
-fn collect_bondings_curve_ckb_amount(type_id: &Bytes,source:Source) -> Result<u64, Error> {
+fn collect_bondings_curve_ckb_amount(curve_ref: &ScriptRef,pool_args: &Bytes,source:Source) -> Result<u64, Error> {
     let mut total_amount = 0u64;
     for (i, cell) in QueryIter::new(load_cell, source).enumerate() {
-        // //debug!("{} cell: {}", source, cell);
-        let lock_code_hash = cell.lock().code_hash();
         let type_hash = cell.type_();
-        // args：xudt_args(32) | type id(32)
-        if lock_code_hash != bondings_curve_code_hash() || type_id[..] != cell.lock().args().raw_data()[32..]  {
-            // //debug!("{} lock_hash: {}, script.code_hash(): {}",source,lock_hash, script.code_hash());
-            // //debug!("{} args: {}, cell.lock().args().raw_data(): {}",source, hex_string(args.as_ref()), hex_string(cell.lock().args().raw_data().as_ref()));
+        // args：token | unique cell type hash(32), 两部分都要一致
+        if ScriptRef::of(&cell.lock()) != *curve_ref || cell.lock().args().raw_data() != pool_args {
             continue;
         }
         
         if type_hash.is_some() {
-            //debug!("{} type_hash is some",source);
             continue;
         }
         // 统计cell的capacity
         let capacity = load_cell_capacity(i, source)?;
-        //debug!("{} capacity: {}", source, capacity);
         total_amount += capacity;
 
     }
@@ -194,46 +141,373 @@ fn collect_bondings_curve_ckb_amount(type_id: &Bytes,source:Source) -> Result<u6
 }
 
 
+// registry cell in cell_deps, 创建池子时必须引用
+fn load_registry() -> Result<Registry, Error> {
+    let index = QueryIter::new(load_cell_type_hash, Source::CellDep)
+        .position(|type_hash| type_hash == Some(REGISTRY_TYPE_HASH))
+        .ok_or(Error::RegistryMissing)?;
+    Ok(Registry::parse(&load_cell_data(index, Source::CellDep)?)?)
+}
+
+// 创建池子时按registry检查: unique liquidity manager和curve_ref是登记的部署, xUDT代币使用登记的xudt
+// 反过来使用登记的xudt的代币也必须声明为xUDT, 否则会跳过发行关闭的证明
+fn check_registry(script: &Script, token: &Token) -> Result<(), Error> {
+    let registry = load_registry()?;
+    let pool = PoolData::parse(&load_cell_data(0, Source::GroupOutput)?)?;
+    trace!("registry", registry = registry);
+    if !registry.unique_liquidity_manager.matches(script) || registry.bondings_curve != pool.curve_ref {
+        return Err(Error::RegistryMismatch);
+    }
+    for cell in QueryIter::new(load_cell, Source::Output) {
+        if let Some(type_script) = cell.type_().to_opt() {
+            if token.is_token(Some(type_script.calc_script_hash().unpack()))
+                && registry.xudt.matches(&type_script) != (token.standard == TokenStandard::Xudt)
+            {
+                return Err(Error::RegistryMismatch);
+            }
+        }
+    }
+    Ok(())
+}
+
+// 交易中该代币的总量
+fn collect_token_amount(token: &Token, source: Source) -> Result<u128, Error> {
+    let mut total_amount = 0u128;
+    for (i, type_hash) in QueryIter::new(load_cell_type_hash, source).enumerate() {
+        if token.is_token(type_hash) {
+            total_amount += token.parse_amount(&load_cell_data(i, source)?)?;
+        }
+    }
+    Ok(total_amount)
+}
+
+// xUDT发行已关闭的证明:
+// 1. owner模式只能是OWNER_MODE_INPUT_TYPE | OWNER_MODE_INPUT_LOCK_NOT, owner_lock_hash是一个Type ID cell的type hash
+// 2. 该owner cell在本交易中被消耗, 并且被销毁或者转入不可解锁的lock
+// Type ID保证owner cell不能被重新创建, 之后再也无法进入owner模式
+fn check_issuance_closed(token: &Token) -> Result<bool, Error> {
+    if token.standard != TokenStandard::Xudt {
+        // sUDT和其他UDT无法证明
+        return Ok(false);
+    }
+    let type_script = QueryIter::new(load_cell_type, Source::Output)
+        .flatten()
+        .find(|type_script| token.is_token(Some(type_script.calc_script_hash().unpack())))
+        .ok_or(Error::InvalidGenesis)?;
+    let args = type_script.args().raw_data();
+    let xudt_args = xudt::parse_args(&args).map_err(TokenError::from)?;
+    if xudt_args.owner_mode_flags() != OWNER_MODE_INPUT_TYPE | OWNER_MODE_INPUT_LOCK_NOT {
+        return Err(Error::IssuanceNotClosed);
+    }
+    let owner_hash: [u8; 32] = xudt_args.owner_lock_hash.try_into().unwrap();
+    let owner_consumed = QueryIter::new(load_cell_type, Source::Input)
+        .flatten()
+        .any(|type_script| ScriptRef::of(&type_script) == TYPE_ID_REF && type_script.calc_script_hash().as_slice() == owner_hash);
+    if !owner_consumed {
+        return Err(Error::IssuanceNotClosed);
+    }
+    for (i, type_hash) in QueryIter::new(load_cell_type_hash, Source::Output).enumerate() {
+        if type_hash == Some(owner_hash) && !ScriptRef::of(&load_cell(i, Source::Output)?.lock()).is_unspendable() {
+            return Err(Error::IssuanceNotClosed);
+        }
+    }
+    Ok(true)
+}
+
+// 创建池子:
+// 1. 代币的全部供应量在本交易中发行, input中不能有该代币
+// 2. output中的代币除了创建者买入的部分全部进入储备cell(lock = curve_ref, args = token | unique cell type hash)
+// 3. 发行总量为TOTAL_XUDT_SUPPLY, unique cell数据与储备cell一致
+// 4. xUDT必须证明发行已关闭, unique cell数据中记录issuance_closed
+// 5. 创建者买入: 整数个代币, 不超过MAX_CREATOR_BUY, 储备ckb不少于曲线价格(含手续费)
+// 6. 同时创建metadata cell
+fn check_genesis(token: &Token) -> Result<(), Error> {
+    if collect_token_amount(token, Source::Input)? > 0 {
+        return Err(Error::InvalidGenesis);
+    }
+    let pool = PoolData::parse(&load_cell_data(0, Source::GroupOutput)?)?;
+    if pool.issuance_closed != check_issuance_closed(token)? {
+        return Err(Error::InvalidGenesis);
+    }
+    let script = load_script()?;
+    let mut metadata_args = script.args().raw_data().to_vec();
+    metadata_args.push(METADATA_FLAG);
+    let metadata_hash = script_hash_with_args(&script, &metadata_args);
+    if !QueryIter::new(load_cell_type_hash, Source::Output).any(|type_hash| type_hash == Some(metadata_hash)) {
+        return Err(Error::InvalidGenesis);
+    }
+    let reserve_args = pool_args(&script)?;
+    let mut reserve_xudt = 0u128;
+    let mut reserve_ckb = 0u128;
+    let mut creator_xudt = 0u128;
+    for (i, cell) in QueryIter::new(load_cell, Source::Output).enumerate() {
+        let lock = cell.lock();
+        let is_reserve = ScriptRef::of(&lock) == pool.curve_ref && lock.args().raw_data() == reserve_args;
+        let type_hash = load_cell_type_hash(i, Source::Output)?;
+        if token.is_token(type_hash) {
+            let amount = token.parse_amount(&load_cell_data(i, Source::Output)?)?;
+            if is_reserve {
+                reserve_xudt += amount;
+            } else {
+                creator_xudt += amount;
+            }
+        } else if is_reserve {
+            // 储备cell只能是代币cell或者普通ckb cell
+            if type_hash.is_some() {
+                return Err(Error::InvalidGenesis);
+            }
+            reserve_ckb += load_cell_capacity(i, Source::Output)? as u128;
+        }
+    }
+    trace!(
+        "genesis",
+        pool = pool,
+        reserve_xudt = reserve_xudt,
+        reserve_ckb = reserve_ckb,
+        creator_xudt = creator_xudt,
+    );
+    if reserve_xudt + creator_xudt != TOTAL_XUDT_SUPPLY || pool.xudt_liquidity != reserve_xudt || pool.ckb_liquidity != reserve_ckb {
+        return Err(Error::InvalidGenesis);
+    }
+    if creator_xudt > 0 {
+        if creator_xudt / XUDT_UNIT * XUDT_UNIT != creator_xudt {
+            return Err(Error::InvalidCreatorBuy);
+        }
+        let cost = creator_buy_cost(creator_xudt, pool.fee_bps()).map_err(|_| Error::InvalidCreatorBuy)?;
+        trace!("creator buy", cost = cost);
+        if reserve_ckb < cost {
+            return Err(Error::InvalidCreatorBuy);
+        }
+    }
+    Ok(())
+}
+
+// type script hash of the same script with other args
+fn script_hash_with_args(script: &Script, args: &[u8]) -> [u8; 32] {
+    let script = script
+        .clone()
+        .as_builder()
+        .args(Bytes::from(args.to_vec()).pack())
+        .build();
+    let mut blake2b = new_blake2b();
+    blake2b.update(script.as_slice());
+    let mut ret = [0; 32];
+    blake2b.finalize(&mut ret);
+    ret
+}
+
+// admin lock (可以是multisig lock) 必须出现在input中
+fn check_admin(governance: &Governance) -> Result<(), Error> {
+    if QueryIter::new(load_cell_lock_hash, Source::Input).any(|lock_hash| lock_hash == governance.admin_lock_hash) {
+        return Ok(());
+    }
+    Err(Error::AdminRequired)
+}
+
+// 费率/管理员变更和curve迁移需要消耗一个内容一致且等待了GOVERNANCE_DELAY的proposal cell
+fn check_proposal(script: &Script, governance: &Governance, curve_ref: Option<ScriptRef>) -> Result<(), Error> {
+    let mut args = script.args().raw_data().to_vec();
+    args.push(PROPOSAL_FLAG);
+    let proposal_hash = script_hash_with_args(script, &args);
+    for (i, type_hash) in QueryIter::new(load_cell_type_hash, Source::Input).enumerate() {
+        if type_hash != Some(proposal_hash) {
+            continue;
+        }
+        let proposal = Proposal::parse(&load_cell_data(i, Source::Input)?)?;
+        if proposal.fee_bps != governance.fee_bps || proposal.admin_lock_hash != governance.admin_lock_hash || proposal.curve_ref != curve_ref {
+            continue;
+        }
+        // 相对since由共识保证, 从proposal cell上链开始计时
+        let since = Since::new(load_input_since(i, Source::Input)?);
+        trace!("proposal", index = i, since = since.as_u64());
+        if since.is_relative() && since.flags_is_valid() {
+            if let Some(LockValue::Timestamp(delay)) = since.extract_lock_value() {
+                if delay >= GOVERNANCE_DELAY {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Err(Error::ProposalNotReady)
+}
+
+// unique cell治理状态变更:
+// 1. 暂停/恢复/紧急提取(graduated) 需要管理员, graduated时储备必须全部转出(见main)
+// 2. 费率/管理员变更, 池子迁移到新的curve引用 需要管理员和到期的proposal, 迁移的目标必须是registry中的curve
+// 3. graduated之后不能再变更
+fn validate_governance(script: &Script) -> Result<(), Error> {
+    if !is_cell_present(0, Source::GroupInput) {
+        // mint
+        let output = PoolData::parse(&load_cell_data(0, Source::GroupOutput)?)?;
+        if output.status() != PoolStatus::Active {
+            return Err(Error::InvalidGovernance);
+        }
+        return Ok(());
+    }
+    if !is_cell_present(0, Source::GroupOutput) {
+        // burn
+        return Ok(());
+    }
+    let input = PoolData::parse(&load_cell_data(0, Source::GroupInput)?)?;
+    let output = PoolData::parse(&load_cell_data(0, Source::GroupOutput)?)?;
+    // issuance_closed在创建池子时写入, 之后不能修改
+    if input.issuance_closed != output.issuance_closed {
+        return Err(Error::InvalidPoolData);
+    }
+    let migration = (input.curve_ref != output.curve_ref).then_some(output.curve_ref);
+    match (input.governance, output.governance) {
+        // 没有管理员的池子不能迁移
+        (None, None) if migration.is_none() => Ok(()),
+        (Some(input), Some(output)) if input == output && migration.is_none() => Ok(()),
+        (Some(input), Some(output)) => {
+            if input.status == PoolStatus::Graduated {
+                return Err(Error::InvalidGovernance);
+            }
+            check_admin(&input)?;
+            if input.fee_bps != output.fee_bps || input.admin_lock_hash != output.admin_lock_hash || migration.is_some() {
+                check_proposal(script, &output, migration)?;
+            }
+            // 和创建池子一样, 迁移的目标必须是registry中登记的curve
+            if let Some(curve_ref) = migration {
+                if load_registry()?.bondings_curve != curve_ref {
+                    return Err(Error::RegistryMismatch);
+                }
+            }
+            Ok(())
+        }
+        // 不能添加或移除管理员
+        _ => Err(Error::InvalidGovernance),
+    }
+}
+
+// proposal cell只能由管理员创建和消耗, unique cell必须同时在input中
+fn validate_proposal(script: &Script) -> Result<(), Error> {
+    let args = script.args().raw_data();
+    let unique_hash = script_hash_with_args(script, &args[..TOKEN_LEN + TYPE_ID_LEN]);
+    let index = QueryIter::new(load_cell_type_hash, Source::Input)
+        .position(|type_hash| type_hash == Some(unique_hash))
+        .ok_or(Error::InvalidGovernance)?;
+    let governance = PoolData::parse(&load_cell_data(index, Source::Input)?)?
+        .governance
+        .ok_or(Error::InvalidGovernance)?;
+    check_admin(&governance)?;
+    for data in QueryIter::new(load_cell_data, Source::GroupOutput) {
+        Proposal::parse(&data)?;
+    }
+    Ok(())
+}
+
+// metadata cell只能在创建池子时创建, 之后lock持有者(创建者)只能设置一次arweave_tx_id, 不能转移或销毁
+fn validate_metadata(script: &Script) -> Result<(), Error> {
+    if is_cell_present(1, Source::GroupInput) || is_cell_present(1, Source::GroupOutput) {
+        return Err(Error::MultipleOutputCells);
+    }
+    if !is_cell_present(0, Source::GroupOutput) {
+        return Err(Error::MetadataImmutable);
+    }
+    let output_data = load_cell_data(0, Source::GroupOutput)?;
+    let output = Metadata::parse(&output_data)?;
+    if is_cell_present(0, Source::GroupInput) {
+        let input_data = load_cell_data(0, Source::GroupInput)?;
+        // lock不变, 只有创建者能设置arweave_tx_id
+        if !Metadata::parse(&input_data)?.can_update_to(&output)
+            || load_cell_lock_hash(0, Source::GroupInput)? != load_cell_lock_hash(0, Source::GroupOutput)?
+        {
+            return Err(Error::MetadataImmutable);
+        }
+        return Ok(());
+    }
+    // 创建: unique cell必须在本交易中创建, arweave_tx_id之后才设置
+    let args = script.args().raw_data();
+    let unique_hash = Some(script_hash_with_args(script, &args[..TOKEN_LEN + TYPE_ID_LEN]));
+    if output.arweave_tx_id.is_some()
+        || !QueryIter::new(load_cell_type_hash, Source::Output).any(|type_hash| type_hash == unique_hash)
+        || QueryIter::new(load_cell_type_hash, Source::Input).any(|type_hash| type_hash == unique_hash)
+    {
+        return Err(Error::InvalidMetadata);
+    }
+    Ok(())
+}
+
 pub fn main() -> Result<(), Error> {
     // Load the type script of the current cell
-    let xudt_args = load_xudt_args_from_args(0)?;
-    let type_id = load_id_from_args(32)?;
-    let type_id_bytes = Bytes::from(type_id.to_vec());
-    let xudt_args_bytes = Bytes::from(xudt_args.to_vec());
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    let (token, type_id, flag) = parse_args(&args)?;
+    trace!("args", token = token, type_id = Hex(&type_id), flag = flag);
+    match flag {
+        Some(PROPOSAL_FLAG) => return validate_proposal(&script),
+        Some(METADATA_FLAG) => return validate_metadata(&script),
+        _ => {}
+    }
     validate_type_id(type_id)?;
+    validate_governance(&script)?;
+    if !is_cell_present(0, Source::GroupInput) {
+        // mint
+        trace!("mint");
+        check_registry(&script, &token)?;
+        return check_genesis(&token);
+    }
+    // 池子创建之后不能再增发代币
+    if collect_token_amount(&token, Source::Output)? > collect_token_amount(&token, Source::Input)? {
+        return Err(Error::TokenSupplyIncreased);
+    }
+    // bondings curve cell的lock args: token | unique cell type hash
+    let pool_args = pool_args(&script)?;
+    // xudt liquidity | ckb liquidity | curve ref | issuance_closed | governance
+    let pool = PoolData::parse(&load_cell_data(0, Source::GroupInput)?)?;
     // input bondings curve cell
-    let bondings_curve_xudt_amount = collect_bondings_curve_xudt_amount(&type_id_bytes,&xudt_args_bytes,Source::Input)?;
-    let bondings_curve_ckb_amount = collect_bondings_curve_ckb_amount(&ckb_args(),Source::Input)?;
-    let mut  should_check_output_liquidity_change = false;
-    // 购买逻辑
-    if bondings_curve_xudt_amount > 0 && bondings_curve_ckb_amount > 0 {
-        should_check_output_liquidity_change = true;
-        // load unique cell data
-        let index = locate_index(Source::Input)?;
-        // xudt liquidity | ckb liquidity
-        let data = load_cell_data(index, Source::Input)?;
-        let xudt_liquidity = u128::from_le_bytes(data[..16].try_into().unwrap());
-        let ckb_liquidity = u128::from_le_bytes(data[16..].try_into().unwrap());
-        if bondings_curve_xudt_amount < xudt_liquidity || (bondings_curve_ckb_amount as u128)  < ckb_liquidity {
+    let bondings_curve_xudt_amount = collect_bondings_curve_xudt_amount(&pool.curve_ref,&pool_args,&token,Source::Input)?;
+    let bondings_curve_ckb_amount = collect_bondings_curve_ckb_amount(&pool.curve_ref,&pool_args,Source::Input)?;
+    trace!("input reserves", pool = pool, xudt = bondings_curve_xudt_amount, ckb = bondings_curve_ckb_amount);
+    // 消耗了储备时, 池子的全部储备cell都必须在input中
+    let reserves_spent = bondings_curve_xudt_amount > 0 || bondings_curve_ckb_amount > 0;
+    if reserves_spent && (bondings_curve_xudt_amount < pool.xudt_liquidity || (bondings_curve_ckb_amount as u128) < pool.ckb_liquidity) {
+        return Err(Error::InvalidLiquidity);
+    }
+
+    if !is_cell_present(0, Source::GroupOutput) {
+        // burn: 储备必须已经全部移出(launch或者紧急提取), 否则储备会被永久锁住
+        trace!("burn", reserves_spent = reserves_spent);
+        if !reserves_spent && (pool.xudt_liquidity > 0 || pool.ckb_liquidity > 0) {
             return Err(Error::InvalidLiquidity);
         }
-
+        let bondings_curve_xudt_amount = collect_bondings_curve_xudt_amount(&pool.curve_ref,&pool_args,&token,Source::Output)?;
+        let bondings_curve_ckb_amount = collect_bondings_curve_ckb_amount(&pool.curve_ref,&pool_args,Source::Output)?;
+        if bondings_curve_xudt_amount > 0 || bondings_curve_ckb_amount > 0 {
+            return Err(Error::InvalidLiquidity);
+        }
+        return Ok(());
     }
 
-    if should_check_output_liquidity_change {
-        let bondings_curve_xudt_amount = collect_bondings_curve_xudt_amount(&type_id_bytes,&xudt_args_bytes,Source::Output)?;
-        let bondings_curve_ckb_amount = collect_bondings_curve_ckb_amount(&ckb_args(),Source::Output)?;
-        if bondings_curve_xudt_amount > 0 && bondings_curve_ckb_amount > 0 {
-            
-            let index = locate_index(Source::Output)?;
-            
-            let data = load_cell_data(index, Source::Output)?;
-            let xudt_liquidity = u128::from_le_bytes(data[..16].try_into().unwrap());
-            let ckb_liquidity = u128::from_le_bytes(data[16..].try_into().unwrap());
-            if bondings_curve_xudt_amount != xudt_liquidity || bondings_curve_ckb_amount as u128 != ckb_liquidity{
-                return Err(Error::InvalidLiquidity);
-            }
+    let output_pool = PoolData::parse(&load_cell_data(0, Source::GroupOutput)?)?;
+    // 紧急提取: 储备必须在同一交易中全部转出, bondings curve检查转给了UTXOSwap
+    if pool.status() != PoolStatus::Graduated
+        && output_pool.status() == PoolStatus::Graduated
+        && (output_pool.xudt_liquidity > 0 || output_pool.ckb_liquidity > 0)
+    {
+        return Err(Error::InvalidGovernance);
+    }
+    // 迁移时按新的curve引用统计
+    let bondings_curve_xudt_amount = collect_bondings_curve_xudt_amount(&output_pool.curve_ref,&pool_args,&token,Source::Output)?;
+    let bondings_curve_ckb_amount = collect_bondings_curve_ckb_amount(&output_pool.curve_ref,&pool_args,Source::Output)?;
+    trace!(
+        "output reserves",
+        pool = output_pool,
+        xudt = bondings_curve_xudt_amount,
+        ckb = bondings_curve_ckb_amount,
+        reserves_spent = reserves_spent,
+    );
+    if reserves_spent {
+        // 数据必须与输出储备一致, 包括储备清空和单边为0的情况
+        if bondings_curve_xudt_amount != output_pool.xudt_liquidity || bondings_curve_ckb_amount as u128 != output_pool.ckb_liquidity {
+            return Err(Error::InvalidLiquidity);
         }
+    } else if bondings_curve_xudt_amount > 0 || bondings_curve_ckb_amount > 0
+        || output_pool.xudt_liquidity != pool.xudt_liquidity || output_pool.ckb_liquidity != pool.ckb_liquidity
+    {
+        // 没有消耗储备(例如只修改治理参数): 不能新增储备, 流动性数据不变
+        return Err(Error::InvalidLiquidity);
     }
 
     Ok(())
@@ -1,9 +1,12 @@
 use ckb_std::error::SysError;
+use ckbfi_common::{
+    error::UNIQUE_LIQUIDITY_MANAGER, metadata::MetadataError, pool::PoolError, registry::RegistryError, token::TokenError,
+};
 
-/// Error
+/// Error, numbered from the start of the contract's range in ckbfi_common::error
 #[repr(i8)]
 pub enum Error {
-    IndexOutOfBound =1,
+    IndexOutOfBound = UNIQUE_LIQUIDITY_MANAGER.base,
     ItemMissing,
     LengthNotEnough,
     Encoding,
@@ -11,8 +14,27 @@ pub enum Error {
     MultipleOutputCells,
     InvalidOutPoint,
     InvalidLiquidity,
+    UnknownTokenStandard,
+    InvalidTokenArgs,
+    InvalidTokenData,
+    InvalidPoolData,
+    InvalidGovernance,
+    AdminRequired,
+    ProposalNotReady,
+    InvalidRegistry,
+    RegistryMismatch,
+    InvalidGenesis,
+    TokenSupplyIncreased,
+    IssuanceNotClosed,
+    InvalidCreatorBuy,
+    InvalidMetadata,
+    MetadataImmutable,
+    RegistryMissing,
 }
 
+// the names in ckbfi_common::error decode the exit codes
+const _: () = assert!(Error::RegistryMissing as i8 + 1 == UNIQUE_LIQUIDITY_MANAGER.next());
+
 impl From<SysError> for Error {
     fn from(err: SysError) -> Self {
         use SysError::*;
@@ -28,3 +50,37 @@ impl From<SysError> for Error {
         }
     }
 }
+
+impl From<TokenError> for Error {
+    fn from(err: TokenError) -> Self {
+        match err {
+            TokenError::UnknownStandard => Self::UnknownTokenStandard,
+            TokenError::InvalidArgs => Self::InvalidTokenArgs,
+            TokenError::InvalidData => Self::InvalidTokenData,
+        }
+    }
+}
+
+impl From<PoolError> for Error {
+    fn from(err: PoolError) -> Self {
+        match err {
+            PoolError::InvalidData => Self::InvalidPoolData,
+        }
+    }
+}
+
+impl From<RegistryError> for Error {
+    fn from(err: RegistryError) -> Self {
+        match err {
+            RegistryError::InvalidData => Self::InvalidRegistry,
+        }
+    }
+}
+
+impl From<MetadataError> for Error {
+    fn from(err: MetadataError) -> Self {
+        match err {
+            MetadataError::InvalidData => Self::InvalidMetadata,
+        }
+    }
+}
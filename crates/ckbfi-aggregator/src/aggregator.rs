@@ -0,0 +1,370 @@
+// Batching and transaction building.
+//
+// The orders of a batch are priced one after another, each at the curve price
+// after the orders before it, like PoolFixture::fill_buys in the tests. The
+// pool is paid for the batch total, which can differ from the sum of the fills
+// by the rounding of the fees. The aggregator's fee cell makes up the
+// difference together with the transaction fee.
+//
+// Transaction layout:
+// inputs:  xudt reserve cells | ckb reserve cells | unique cell | order cells | fee cell
+// outputs: xudt reserve cell | ckb reserve cell | unique cell | user cells | change cell
+
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionBuilder, TransactionView},
+    packed::{CellDep, CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use ckbfi_common::{pool::PoolStatus, registry::Registry, script_ref::ScriptRef};
+use ckbfi_curve::{
+    quote_buy, quote_sell, with_buy_fee, with_sell_fee, without_buy_fee, xudt_for_ckb, CurveError, XUDT_UNIT,
+};
+use std::collections::BTreeMap;
+
+use crate::{
+    error::Error,
+    indexer::{Indexer, LiveCell, SearchKey},
+    order::{Order, Side},
+    pool::Pool,
+};
+
+const UDT_DATA_LEN: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // type script of the registry cell, the unique_liquidity_manager and
+    // order-contract deployments are read from it
+    pub registry: Script,
+    // code cells of the contracts, the token and the aggregator lock, and the
+    // registry cell the contracts look up
+    pub cell_deps: Vec<CellDep>,
+    // lock of the unique cells and of the cells paying the transaction fee
+    pub lock: Script,
+    // shannons
+    pub tx_fee: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    // the indexer doesn't know the lock script of the user
+    UnknownUser,
+    // the order can't get the xudt it asks for at the current price
+    Slippage,
+    // the order cell can't hold the user's xudt cell
+    NotEnoughCapacity,
+    // the batch fills an order of the same user with other args
+    OtherOrderGroup,
+    Curve(CurveError),
+}
+
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub order: Order,
+    pub user_lock: Script,
+    // xudt the user buys or sells
+    pub xudt: u128,
+    // ckb the user pays for a buy or receives for a sell
+    pub ckb: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub pool: Pool,
+    pub side: Side,
+    pub fills: Vec<Fill>,
+    // orders left for a later round
+    pub skipped: Vec<(OutPoint, SkipReason)>,
+    // reserves after the batch
+    pub xudt_reserve: u128,
+    pub ckb_reserve: u64,
+}
+
+pub struct Aggregator {
+    pub config: Config,
+}
+
+fn occupied(output: &CellOutput, data_len: usize) -> u64 {
+    let data_capacity = Capacity::bytes(data_len).expect("data capacity");
+    output.occupied_capacity(data_capacity).expect("occupied capacity").as_u64()
+}
+
+fn cell(lock: &Script, type_: Option<&Script>, capacity: u64) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(capacity.pack())
+        .lock(lock.clone())
+        .type_(type_.cloned().pack())
+        .build()
+}
+
+// script running the code of script_ref, with empty args
+fn script_of(script_ref: &ScriptRef) -> Script {
+    Script::new_builder()
+        .code_hash(script_ref.code_hash.pack())
+        .hash_type(script_ref.hash_type.into())
+        .build()
+}
+
+fn udt_data(amount: u128) -> Bytes {
+    Bytes::from(amount.to_le_bytes().to_vec())
+}
+
+// The order-contract can't tell which order a user cell belongs to, so the
+// orders of a user in one transaction must have the same args.
+fn in_other_group(fills: &[Fill], order: &Order) -> bool {
+    fills
+        .iter()
+        .any(|fill| fill.order.user_lock_hash == order.user_lock_hash && fill.order.cell.output.lock() != order.cell.output.lock())
+}
+
+impl Aggregator {
+    pub fn new(config: Config) -> Self {
+        Aggregator { config }
+    }
+
+    // The live registry cell, its type script has to match the config's
+    // exactly.
+    pub fn registry(&self, indexer: &impl Indexer) -> Result<Registry, Error> {
+        let cell = indexer
+            .get_cells(&SearchKey::type_(self.config.registry.clone()))?
+            .into_iter()
+            .find(|cell| cell.output.type_().to_opt().as_ref() == Some(&self.config.registry))
+            .ok_or(Error::RegistryMissing)?;
+        Registry::parse(&cell.data).map_err(|_| Error::InvalidRegistry)
+    }
+
+    // One batch per active pool with order cells waiting for it, including
+    // batches where every order was skipped.
+    pub fn plan(&self, indexer: &impl Indexer) -> Result<Vec<Batch>, Error> {
+        let registry = self.registry(indexer)?;
+        let key = SearchKey::lock(script_of(&registry.order));
+        let mut orders: BTreeMap<[u8; 32], Vec<Order>> = BTreeMap::new();
+        for order in indexer.get_cells(&key)?.into_iter().filter_map(Order::parse) {
+            orders.entry(order.curve_lock_hash).or_default().push(order);
+        }
+
+        let mut batches = Vec::new();
+        for pool in Pool::find_all(indexer, &script_of(&registry.unique_liquidity_manager))? {
+            if pool.data.status() != PoolStatus::Active {
+                continue;
+            }
+            let curve_lock_hash: [u8; 32] = pool.curve_lock_hash().unpack();
+            let Some(pool_orders) = orders.remove(&curve_lock_hash) else {
+                continue;
+            };
+            let (buys, sells): (Vec<_>, Vec<_>) = pool_orders
+                .into_iter()
+                .filter(|order| order.token == pool.token)
+                .partition(|order| order.side() == Side::Buy);
+            let mut batch = self.fill_buys(indexer, &pool, buys)?;
+            if batch.fills.is_empty() && !sells.is_empty() {
+                let skipped = batch.skipped;
+                batch = self.fill_sells(indexer, &pool, sells)?;
+                batch.skipped.extend(skipped);
+            }
+            batches.push(batch);
+        }
+        Ok(batches)
+    }
+
+    fn user_lock(&self, indexer: &impl Indexer, order: &Order) -> Result<Option<Script>, Error> {
+        indexer.get_lock_script(&order.user_lock_hash.pack())
+    }
+
+    fn fill_buys(&self, indexer: &impl Indexer, pool: &Pool, orders: Vec<Order>) -> Result<Batch, Error> {
+        let fee_bps = pool.data.fee_bps();
+        let mut reserve = pool.xudt_reserve();
+        let mut fills = Vec::new();
+        let mut skipped = Vec::new();
+        for order in orders {
+            if in_other_group(&fills, &order) {
+                skipped.push((order.cell.out_point, SkipReason::OtherOrderGroup));
+                continue;
+            }
+            let Some(user_lock) = self.user_lock(indexer, &order)? else {
+                skipped.push((order.cell.out_point, SkipReason::UnknownUser));
+                continue;
+            };
+            // the user's xudt cell is paid from the order capacity as well
+            let user_cell = occupied(&cell(&user_lock, Some(&pool.token_type), 0), UDT_DATA_LEN);
+            let Some(mut budget) = order.cell.capacity().checked_sub(user_cell) else {
+                skipped.push((order.cell.out_point, SkipReason::NotEnoughCapacity));
+                continue;
+            };
+            if let Some(ckb_in) = order.ckb_in {
+                budget = budget.min(ckb_in);
+            }
+            match buy_amount(reserve, &order, budget, fee_bps) {
+                Ok(Some((xudt, ckb))) => {
+                    reserve -= xudt;
+                    fills.push(Fill {
+                        order,
+                        user_lock,
+                        xudt,
+                        ckb,
+                    });
+                }
+                Ok(None) => skipped.push((order.cell.out_point, SkipReason::Slippage)),
+                Err(err) => skipped.push((order.cell.out_point, SkipReason::Curve(err))),
+            }
+        }
+        let sold = pool.xudt_reserve() - reserve;
+        let paid = if sold > 0 {
+            with_buy_fee(quote_buy(pool.xudt_reserve(), sold)?, fee_bps)? as u64
+        } else {
+            0
+        };
+        Ok(Batch {
+            pool: pool.clone(),
+            side: Side::Buy,
+            fills,
+            skipped,
+            xudt_reserve: reserve,
+            ckb_reserve: pool.ckb_reserve() + paid,
+        })
+    }
+
+    fn fill_sells(&self, indexer: &impl Indexer, pool: &Pool, orders: Vec<Order>) -> Result<Batch, Error> {
+        let fee_bps = pool.data.fee_bps();
+        // the pool keeps a ckb reserve cell
+        let min_reserve = occupied(&cell(&pool.curve_lock, None, 0), 0);
+        let mut bought = 0u128;
+        let mut paid = 0u64;
+        let mut fills = Vec::new();
+        let mut skipped = Vec::new();
+        for order in orders {
+            if in_other_group(&fills, &order) {
+                skipped.push((order.cell.out_point, SkipReason::OtherOrderGroup));
+                continue;
+            }
+            let Some(user_lock) = self.user_lock(indexer, &order)? else {
+                skipped.push((order.cell.out_point, SkipReason::UnknownUser));
+                continue;
+            };
+            // sell orders receive ckb, they can't ask for xudt
+            if order.min_amount() > 0 {
+                skipped.push((order.cell.out_point, SkipReason::Slippage));
+                continue;
+            }
+            let quote = quote_sell(pool.xudt_reserve() + bought, order.xudt)
+                .and_then(|ckb| with_sell_fee(ckb, fee_bps))
+                .and_then(|proceeds| {
+                    let total = with_sell_fee(quote_sell(pool.xudt_reserve(), bought + order.xudt)?, fee_bps)?;
+                    if u128::from(pool.ckb_reserve()) < total + u128::from(min_reserve) {
+                        return Err(CurveError::InsufficientLiquidity);
+                    }
+                    Ok((proceeds as u64, total as u64))
+                });
+            match quote {
+                Ok((proceeds, total)) => {
+                    bought += order.xudt;
+                    paid = total;
+                    fills.push(Fill {
+                        xudt: order.xudt,
+                        ckb: proceeds,
+                        order,
+                        user_lock,
+                    });
+                }
+                Err(err) => skipped.push((order.cell.out_point, SkipReason::Curve(err))),
+            }
+        }
+        Ok(Batch {
+            pool: pool.clone(),
+            side: Side::Sell,
+            fills,
+            skipped,
+            xudt_reserve: pool.xudt_reserve() + bought,
+            ckb_reserve: pool.ckb_reserve() - paid,
+        })
+    }
+
+    // The transaction filling batch, fee_cell pays the transaction fee. The
+    // witnesses are left for the caller to sign the unique cell and fee cell.
+    pub fn build(&self, batch: &Batch, fee_cell: &LiveCell) -> Result<TransactionView, Error> {
+        let pool = &batch.pool;
+        let mut inputs: Vec<&LiveCell> = pool.xudt_cells.iter().chain(&pool.ckb_cells).collect();
+        inputs.push(&pool.unique_cell);
+        inputs.extend(batch.fills.iter().map(|fill| &fill.order.cell));
+        inputs.push(fee_cell);
+
+        let xudt_capacity = pool.xudt_cells.iter().map(LiveCell::capacity).sum();
+        let mut outputs = vec![
+            (
+                cell(&pool.curve_lock, Some(&pool.token_type), xudt_capacity),
+                udt_data(batch.xudt_reserve),
+            ),
+            (cell(&pool.curve_lock, None, batch.ckb_reserve), Bytes::new()),
+            (
+                pool.unique_cell.output.clone(),
+                pool.data_with_reserves(batch.xudt_reserve, batch.ckb_reserve),
+            ),
+        ];
+        for fill in &batch.fills {
+            let capacity = fill.order.cell.capacity();
+            outputs.push(match batch.side {
+                Side::Buy => (
+                    cell(&fill.user_lock, Some(&pool.token_type), capacity - fill.ckb),
+                    udt_data(fill.xudt),
+                ),
+                Side::Sell => (cell(&fill.user_lock, None, capacity + fill.ckb), Bytes::new()),
+            });
+        }
+
+        let input_capacity: u64 = inputs.iter().map(|cell| cell.capacity()).sum();
+        let output_capacity: u64 = outputs.iter().map(|(output, _)| Unpack::<u64>::unpack(&output.capacity())).sum();
+        let change = cell(&self.config.lock, None, 0);
+        let change_capacity = input_capacity
+            .checked_sub(output_capacity + self.config.tx_fee)
+            .filter(|&capacity| capacity >= occupied(&change, 0))
+            .ok_or(Error::FeeCellTooSmall)?;
+        outputs.push((change.as_builder().capacity(change_capacity.pack()).build(), Bytes::new()));
+
+        let (outputs, outputs_data): (Vec<_>, Vec<_>) = outputs.into_iter().unzip();
+        Ok(TransactionBuilder::default()
+            .inputs(inputs.into_iter().map(LiveCell::input))
+            .outputs(outputs)
+            .outputs_data(outputs_data.pack())
+            .cell_deps(self.config.cell_deps.clone())
+            .build())
+    }
+
+    // Plan a round and build the transactions of the batches with fills, each
+    // paid by its own plain cell of the aggregator lock.
+    pub fn run(&self, indexer: &impl Indexer) -> Result<Vec<(Batch, TransactionView)>, Error> {
+        let mut fee_cells = indexer
+            .get_cells(&SearchKey::lock(self.config.lock.clone()))?
+            .into_iter()
+            .filter(|cell| cell.output.lock() == self.config.lock && cell.output.type_().is_none() && cell.data.is_empty());
+        let mut txs = Vec::new();
+        for batch in self.plan(indexer)? {
+            if batch.fills.is_empty() {
+                continue;
+            }
+            let tx = loop {
+                let fee_cell = fee_cells.next().ok_or(Error::NoFeeCell)?;
+                match self.build(&batch, &fee_cell) {
+                    Err(Error::FeeCellTooSmall) => continue,
+                    result => break result?,
+                }
+            };
+            txs.push((batch, tx));
+        }
+        Ok(txs)
+    }
+}
+
+// Whole tokens an order buys from a pool holding reserve, and what they cost
+// with the fee. None when that's less than the order accepts.
+fn buy_amount(reserve: u128, order: &Order, budget: u64, fee_bps: u16) -> Result<Option<(u128, u64)>, CurveError> {
+    let mut amount = xudt_for_ckb(reserve, without_buy_fee(u128::from(budget), fee_bps)?)?;
+    if order.ckb_in.is_none() {
+        amount = amount.min(order.desired_amount / XUDT_UNIT * XUDT_UNIT);
+    }
+    if amount == 0 || amount < order.min_amount() {
+        return Ok(None);
+    }
+    // without_buy_fee rounds down, so the cost with the fee stays within budget
+    let cost = with_buy_fee(quote_buy(reserve, amount)?, fee_bps)?;
+    Ok(Some((amount, cost as u64)))
+}
@@ -0,0 +1,38 @@
+use ckbfi_curve::CurveError;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // the indexer couldn't answer, e.g. the node is unreachable
+    Indexer(String),
+    Curve(CurveError),
+    // no live cell has the registry type script of the config
+    RegistryMissing,
+    // the registry cell data is too short or malformed
+    InvalidRegistry,
+    // no cell of the aggregator lock can pay the transaction fee
+    NoFeeCell,
+    // the fee cell can't pay the transaction fee and keep a change cell
+    FeeCellTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Indexer(err) => write!(f, "indexer: {}", err),
+            Error::Curve(err) => write!(f, "curve: {}", err),
+            Error::RegistryMissing => write!(f, "registry missing"),
+            Error::InvalidRegistry => write!(f, "invalid registry"),
+            Error::NoFeeCell => write!(f, "no fee cell"),
+            Error::FeeCellTooSmall => write!(f, "fee cell too small"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<CurveError> for Error {
+    fn from(err: CurveError) -> Self {
+        Error::Curve(err)
+    }
+}
@@ -0,0 +1,82 @@
+// Source of live cells.
+//
+// The search follows get_cells of the CKB indexer RPC: cells whose lock or
+// type script runs the code of `script` with args starting with its args. A
+// node backed implementation pages through get_cells, the tests crate
+// implements it over a ckb-testtool Context.
+
+use ckb_types::{
+    bytes::Bytes,
+    packed::{Byte32, CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptType {
+    Lock,
+    Type,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchKey {
+    pub script: Script,
+    pub script_type: ScriptType,
+}
+
+impl SearchKey {
+    pub fn lock(script: Script) -> Self {
+        SearchKey {
+            script,
+            script_type: ScriptType::Lock,
+        }
+    }
+
+    pub fn type_(script: Script) -> Self {
+        SearchKey {
+            script,
+            script_type: ScriptType::Type,
+        }
+    }
+
+    pub fn matches(&self, output: &CellOutput) -> bool {
+        let script = match self.script_type {
+            ScriptType::Lock => Some(output.lock()),
+            ScriptType::Type => output.type_().to_opt(),
+        };
+        script.is_some_and(|script| {
+            script.code_hash() == self.script.code_hash()
+                && script.hash_type() == self.script.hash_type()
+                && script.args().raw_data().starts_with(&self.script.args().raw_data())
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LiveCell {
+    pub out_point: OutPoint,
+    pub output: CellOutput,
+    pub data: Bytes,
+}
+
+impl LiveCell {
+    pub fn capacity(&self) -> u64 {
+        self.output.capacity().unpack()
+    }
+
+    pub fn input(&self) -> CellInput {
+        CellInput::new_builder()
+            .previous_output(self.out_point.clone())
+            .build()
+    }
+}
+
+pub trait Indexer {
+    fn get_cells(&self, key: &SearchKey) -> Result<Vec<LiveCell>, Error>;
+
+    // Lock script of a lock hash. Orders only carry the hash of the user
+    // lock, an indexer learns the script from the inputs of the transaction
+    // creating the order.
+    fn get_lock_script(&self, lock_hash: &Byte32) -> Result<Option<Script>, Error>;
+}
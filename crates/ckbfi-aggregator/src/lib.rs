@@ -0,0 +1,24 @@
+// Reference implementation of the ckbfi aggregator.
+//
+// The aggregator finds the pools and the order cells waiting for them through
+// an Indexer, batches the orders of each pool and builds the transaction
+// filling them. Prices come from ckbfi-curve, the same code the
+// bondings-curve-contract checks the trades with.
+//
+// One round fills the buy orders of every pool, or its sell orders when it has
+// no buy orders to fill. A batch spends the pool's cells, so the next round
+// has to wait for the transactions of this one to be committed. Signing,
+// sending and waiting are left to the caller, the transactions have no
+// witnesses.
+
+mod aggregator;
+pub mod error;
+pub mod indexer;
+pub mod order;
+pub mod pool;
+
+pub use aggregator::{Aggregator, Batch, Config, Fill, SkipReason};
+pub use error::Error;
+pub use indexer::{Indexer, LiveCell, ScriptType, SearchKey};
+pub use order::{Order, Side};
+pub use pool::Pool;
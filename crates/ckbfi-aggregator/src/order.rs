@@ -0,0 +1,89 @@
+// Order cells of the order-contract.
+//
+// args: bondings_curve_lock_hash(32) | user_lock_hash(32) | token(33) | slip_point(2) | desired_amount(16) | ckb_in(8, optional)
+//
+// A plain order cell buys, its capacity pays for the xudt and the user's xudt
+// cell. An order cell holding the token sells it.
+
+use ckb_types::prelude::*;
+use ckbfi_common::token::{Token, TOKEN_LEN};
+
+use crate::indexer::LiveCell;
+
+const LOCK_HASH_LEN: usize = 32;
+const TOKEN_END: usize = LOCK_HASH_LEN * 2 + TOKEN_LEN;
+const ARGS_LEN: usize = TOKEN_END + 2 + 16;
+const CKB_IN_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone)]
+pub struct Order {
+    pub cell: LiveCell,
+    pub curve_lock_hash: [u8; 32],
+    pub user_lock_hash: [u8; 32],
+    pub token: Token,
+    pub slip_point: u16,
+    pub desired_amount: u128,
+    // exact-in buy orders spend at most ckb_in
+    pub ckb_in: Option<u64>,
+    // xudt in the order cell, sell orders only
+    pub xudt: u128,
+}
+
+impl Order {
+    // None for cells the order-contract would reject, or holding another token.
+    pub fn parse(cell: LiveCell) -> Option<Order> {
+        let args = cell.output.lock().args().raw_data();
+        if args.len() != ARGS_LEN && args.len() != ARGS_LEN + CKB_IN_LEN {
+            return None;
+        }
+        let token = Token::parse(&args[LOCK_HASH_LEN * 2..TOKEN_END]).ok()?;
+        let slip_point = u16::from_be_bytes(args[TOKEN_END..TOKEN_END + 2].try_into().unwrap());
+        let desired_amount = u128::from_be_bytes(args[TOKEN_END + 2..ARGS_LEN].try_into().unwrap());
+        // the order-contract fails computing min_amount for these, only a cancel unlocks them
+        if slip_point > 10000 || desired_amount.checked_mul(u128::from(slip_point)).is_none() {
+            return None;
+        }
+        let type_hash = cell
+            .output
+            .type_()
+            .to_opt()
+            .map(|type_| type_.calc_script_hash().unpack());
+        let xudt = match type_hash {
+            None => 0,
+            Some(_) if token.is_token(type_hash) => token.parse_amount(&cell.data).ok()?,
+            Some(_) => return None,
+        };
+        Some(Order {
+            curve_lock_hash: args[..LOCK_HASH_LEN].try_into().unwrap(),
+            user_lock_hash: args[LOCK_HASH_LEN..LOCK_HASH_LEN * 2].try_into().unwrap(),
+            token,
+            slip_point,
+            desired_amount,
+            ckb_in: args
+                .get(ARGS_LEN..)
+                .filter(|ckb_in| !ckb_in.is_empty())
+                .map(|ckb_in| u64::from_be_bytes(ckb_in.try_into().unwrap())),
+            xudt,
+            cell,
+        })
+    }
+
+    pub fn side(&self) -> Side {
+        if self.cell.output.type_().is_some() {
+            Side::Sell
+        } else {
+            Side::Buy
+        }
+    }
+
+    // Least xudt the user has to receive, as the order-contract computes it.
+    pub fn min_amount(&self) -> u128 {
+        self.desired_amount - self.desired_amount * u128::from(self.slip_point) / 10000
+    }
+}
@@ -0,0 +1,131 @@
+// A pool as the aggregator sees it: the unique cell, the reserve cells locked
+// by the curve lock and the token they hold.
+//
+// The curve lock is not stored anywhere, it is rebuilt from curve_ref in the
+// pool data with args token | unique_type_hash like bondings-curve-contract
+// expects.
+
+use ckb_types::{
+    bytes::Bytes,
+    packed::{Byte32, Script},
+    prelude::*,
+};
+use ckbfi_common::{
+    pool::{PoolData, LIQUIDITY_LEN},
+    token::{Token, TOKEN_LEN},
+};
+
+use crate::{
+    error::Error,
+    indexer::{Indexer, LiveCell, SearchKey},
+};
+
+#[derive(Debug, Clone)]
+pub struct Pool {
+    pub token: Token,
+    pub token_type: Script,
+    pub unique_cell: LiveCell,
+    pub data: PoolData,
+    pub curve_lock: Script,
+    pub xudt_cells: Vec<LiveCell>,
+    pub ckb_cells: Vec<LiveCell>,
+}
+
+impl Pool {
+    // Every pool whose unique cell runs the code of manager. The metadata
+    // cells of the manager have longer args and are left out.
+    pub fn find_all(indexer: &impl Indexer, manager: &Script) -> Result<Vec<Pool>, Error> {
+        let key = SearchKey::type_(manager.clone().as_builder().args(Bytes::new().pack()).build());
+        let mut pools = Vec::new();
+        for cell in indexer.get_cells(&key)? {
+            if let Some(pool) = Pool::load(indexer, cell)? {
+                pools.push(pool);
+            }
+        }
+        Ok(pools)
+    }
+
+    // None when the cell is no unique cell or its reserve cells don't add up
+    // to its pool data. Graduated pools have no reserve cells left.
+    pub fn load(indexer: &impl Indexer, unique_cell: LiveCell) -> Result<Option<Pool>, Error> {
+        let Some(unique_type) = unique_cell.output.type_().to_opt() else {
+            return Ok(None);
+        };
+        let args = unique_type.args().raw_data();
+        if args.len() != TOKEN_LEN + 32 {
+            return Ok(None);
+        }
+        let (Ok(token), Ok(data)) = (Token::parse(&args[..TOKEN_LEN]), PoolData::parse(&unique_cell.data)) else {
+            return Ok(None);
+        };
+        let mut curve_args = args[..TOKEN_LEN].to_vec();
+        curve_args.extend_from_slice(unique_type.calc_script_hash().as_slice());
+        let curve_lock = Script::new_builder()
+            .code_hash(data.curve_ref.code_hash.pack())
+            .hash_type(data.curve_ref.hash_type.into())
+            .args(Bytes::from(curve_args).pack())
+            .build();
+
+        let mut token_type = None;
+        let (mut xudt_cells, mut ckb_cells) = (Vec::new(), Vec::new());
+        let (mut xudt, mut ckb) = (0u128, 0u128);
+        for cell in indexer.get_cells(&SearchKey::lock(curve_lock.clone()))? {
+            // the search matches args by prefix
+            if cell.output.lock() != curve_lock {
+                continue;
+            }
+            match cell.output.type_().to_opt() {
+                None => {
+                    ckb += u128::from(cell.capacity());
+                    ckb_cells.push(cell);
+                }
+                Some(type_) if token.is_token(Some(type_.calc_script_hash().unpack())) => {
+                    let Ok(amount) = token.parse_amount(&cell.data) else {
+                        return Ok(None);
+                    };
+                    xudt += amount;
+                    token_type = Some(type_);
+                    xudt_cells.push(cell);
+                }
+                // anything else the curve lock holds isn't part of the reserves
+                Some(_) => {}
+            }
+        }
+        let Some(token_type) = token_type else {
+            return Ok(None);
+        };
+        if xudt != data.xudt_liquidity || ckb != data.ckb_liquidity {
+            return Ok(None);
+        }
+        Ok(Some(Pool {
+            token,
+            token_type,
+            unique_cell,
+            data,
+            curve_lock,
+            xudt_cells,
+            ckb_cells,
+        }))
+    }
+
+    pub fn curve_lock_hash(&self) -> Byte32 {
+        self.curve_lock.calc_script_hash()
+    }
+
+    pub fn xudt_reserve(&self) -> u128 {
+        self.data.xudt_liquidity
+    }
+
+    // The ckb reserve is the capacity of cells, it fits in u64.
+    pub fn ckb_reserve(&self) -> u64 {
+        self.data.ckb_liquidity as u64
+    }
+
+    // Unique cell data holding the new reserves, the rest stays the same.
+    pub fn data_with_reserves(&self, xudt: u128, ckb: u64) -> Bytes {
+        let mut data = xudt.to_le_bytes().to_vec();
+        data.extend_from_slice(&u128::from(ckb).to_le_bytes());
+        data.extend_from_slice(&self.unique_cell.data[LIQUIDITY_LEN..]);
+        data.into()
+    }
+}
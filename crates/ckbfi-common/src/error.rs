@@ -0,0 +1,180 @@
+// Exit codes of the ckbfi contracts.
+//
+// Every contract numbers its Error enum from the start of its own range, so an
+// exit code alone tells which contract failed and why:
+//
+//   10..=39   bondings-curve-contract
+//   40..=59   order-contract
+//   60..=99   unique_liquidity_manager
+//
+// 1..=9 and 100..=127 are reserved, negative codes come from ckb-std (panics
+// exit with -1) and the other scripts of a transaction. New variants are
+// appended to the enum and to the names below, existing codes never change.
+// The contracts check at compile time that their enum ends where the names do.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCodes {
+    pub contract: &'static str,
+    // code of the first variant
+    pub base: i8,
+    // last code of the range
+    pub end: i8,
+    // variant names in code order
+    pub names: &'static [&'static str],
+}
+
+pub const BONDINGS_CURVE: ErrorCodes = ErrorCodes {
+    contract: "bondings-curve-contract",
+    base: 10,
+    end: 39,
+    names: &[
+        "IndexOutOfBound",
+        "ItemMissing",
+        "LengthNotEnough",
+        "Encoding",
+        "OutPutValidationFailure",
+        "UnableRemove",
+        "UserPayCkbNotEnough",
+        "UserPayXudtNotEnough",
+        "OutputCkbInvalid",
+        "PermissionDenied",
+        "InputValidationFailure",
+        "UnknownTokenStandard",
+        "InvalidTokenArgs",
+        "InvalidTokenData",
+        "PriceOutOfRange",
+        "UserReceiveXudtNotEnough",
+        "InvalidPoolData",
+        "PoolNotActive",
+        "InvalidRegistry",
+        "RegistryMissing",
+    ],
+};
+
+pub const ORDER: ErrorCodes = ErrorCodes {
+    contract: "order-contract",
+    base: 40,
+    end: 59,
+    names: &[
+        "IndexOutOfBound",
+        "ItemMissing",
+        "LengthNotEnough",
+        "OutputInvalid",
+        "Encoding",
+        "MissMatchBondingsCell",
+        "InvalidArgs",
+        "UnknownTokenStandard",
+        "InvalidTokenArgs",
+        "InvalidTokenData",
+        "UserPayCkbTooMuch",
+        "MultipleOrderGroups",
+    ],
+};
+
+pub const UNIQUE_LIQUIDITY_MANAGER: ErrorCodes = ErrorCodes {
+    contract: "unique_liquidity_manager",
+    base: 60,
+    end: 99,
+    names: &[
+        "IndexOutOfBound",
+        "ItemMissing",
+        "LengthNotEnough",
+        "Encoding",
+        "InvalidArgs",
+        "MultipleOutputCells",
+        "InvalidOutPoint",
+        "InvalidLiquidity",
+        "UnknownTokenStandard",
+        "InvalidTokenArgs",
+        "InvalidTokenData",
+        "InvalidPoolData",
+        "InvalidGovernance",
+        "AdminRequired",
+        "ProposalNotReady",
+        "InvalidRegistry",
+        "RegistryMismatch",
+        "InvalidGenesis",
+        "TokenSupplyIncreased",
+        "IssuanceNotClosed",
+        "InvalidCreatorBuy",
+        "InvalidMetadata",
+        "MetadataImmutable",
+        "RegistryMissing",
+    ],
+};
+
+pub const CONTRACTS: [ErrorCodes; 3] = [BONDINGS_CURVE, ORDER, UNIQUE_LIQUIDITY_MANAGER];
+
+impl ErrorCodes {
+    // exit code of the variant following the last named one
+    pub const fn next(&self) -> i8 {
+        self.base + self.names.len() as i8
+    }
+
+    pub fn contains(&self, code: i8) -> bool {
+        (self.base..=self.end).contains(&code)
+    }
+
+    pub fn name(&self, code: i8) -> Option<&'static str> {
+        if !self.contains(code) {
+            return None;
+        }
+        self.names.get((code - self.base) as usize).copied()
+    }
+
+    // const so the tests can name their expected codes
+    pub const fn code(&self, name: &str) -> Option<i8> {
+        let mut index = 0;
+        while index < self.names.len() {
+            if str_eq(self.names[index], name) {
+                return Some(self.base + index as i8);
+            }
+            index += 1;
+        }
+        None
+    }
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedError {
+    pub contract: &'static str,
+    pub name: &'static str,
+}
+
+// Contract and Error variant of an exit code, None for codes outside the
+// ckbfi ranges or past the last variant of a range.
+pub fn decode(code: i8) -> Option<DecodedError> {
+    let codes = CONTRACTS.iter().find(|codes| codes.contains(code))?;
+    Some(DecodedError {
+        contract: codes.contract,
+        name: codes.name(code)?,
+    })
+}
+
+// The ranges don't overlap and every contract fits its range.
+const _: () = {
+    let mut i = 0;
+    while i < CONTRACTS.len() {
+        assert!(CONTRACTS[i].base <= CONTRACTS[i].end);
+        assert!(CONTRACTS[i].next() - 1 <= CONTRACTS[i].end);
+        if i > 0 {
+            assert!(CONTRACTS[i - 1].end < CONTRACTS[i].base);
+        }
+        i += 1;
+    }
+};
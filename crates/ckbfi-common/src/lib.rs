@@ -0,0 +1,16 @@
+#![no_std]
+
+// Helpers shared by the ckbfi contracts. Everything here works on plain byte
+// slices so it can be used from contracts as well as from off-chain code.
+
+extern crate alloc;
+
+pub mod error;
+pub mod metadata;
+pub mod molecule;
+pub mod pool;
+pub mod registry;
+pub mod script_ref;
+pub mod token;
+pub mod trace;
+pub mod xudt;
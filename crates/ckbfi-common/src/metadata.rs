@@ -0,0 +1,89 @@
+// Token metadata cell of a pool.
+//
+// table TokenMetadata {
+//     name: Bytes,
+//     symbol: Bytes,
+//     decimals: byte,
+//     tweet: Bytes,          // X/Twitter url or id the token was created from
+//     arweave_tx_id: Bytes,  // empty until the X content is uploaded to Arweave
+// }
+//
+// The metadata cell is typed by the unique liquidity manager with args
+// token | type_id | METADATA_FLAG, so it belongs to the pool with the same
+// type_id. It is created in the pool genesis transaction, and afterwards only
+// arweave_tx_id can be set, exactly once.
+
+use ckb_std::ckb_types::{packed::BytesReader, prelude::*};
+
+use crate::molecule::split_dynamic;
+
+pub const METADATA_FLAG: u8 = 2;
+
+const METADATA_FIELDS: usize = 5;
+// the curve prices whole tokens of 8 decimals
+pub const METADATA_DECIMALS: u8 = 8;
+pub const MAX_NAME_LEN: usize = 64;
+pub const MAX_SYMBOL_LEN: usize = 16;
+pub const MAX_TWEET_LEN: usize = 256;
+// raw 32 bytes, not the base64url form
+pub const ARWEAVE_TX_ID_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataError {
+    InvalidData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata<'a> {
+    pub name: &'a [u8],
+    pub symbol: &'a [u8],
+    pub decimals: u8,
+    pub tweet: &'a [u8],
+    pub arweave_tx_id: Option<&'a [u8]>,
+}
+
+// raw data of a molecule Bytes field
+fn bytes_field(field: &[u8], max_len: usize) -> Result<&[u8], MetadataError> {
+    BytesReader::verify(field, false).map_err(|_| MetadataError::InvalidData)?;
+    let raw = &field[4..];
+    if raw.len() > max_len {
+        return Err(MetadataError::InvalidData);
+    }
+    Ok(raw)
+}
+
+impl<'a> Metadata<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, MetadataError> {
+        let fields = split_dynamic(data).ok_or(MetadataError::InvalidData)?;
+        if fields.len() != METADATA_FIELDS || fields[2].len() != 1 {
+            return Err(MetadataError::InvalidData);
+        }
+        let name = bytes_field(fields[0], MAX_NAME_LEN)?;
+        let symbol = bytes_field(fields[1], MAX_SYMBOL_LEN)?;
+        let tweet = bytes_field(fields[3], MAX_TWEET_LEN)?;
+        if name.is_empty() || symbol.is_empty() || tweet.is_empty() || fields[2][0] != METADATA_DECIMALS {
+            return Err(MetadataError::InvalidData);
+        }
+        let arweave_tx_id = match bytes_field(fields[4], ARWEAVE_TX_ID_LEN)? {
+            [] => None,
+            id if id.len() == ARWEAVE_TX_ID_LEN => Some(id),
+            _ => return Err(MetadataError::InvalidData),
+        };
+        Ok(Metadata {
+            name,
+            symbol,
+            decimals: fields[2][0],
+            tweet,
+            arweave_tx_id,
+        })
+    }
+
+    // Whether other is this metadata with at most arweave_tx_id set.
+    pub fn can_update_to(&self, other: &Metadata) -> bool {
+        self.name == other.name
+            && self.symbol == other.symbol
+            && self.decimals == other.decimals
+            && self.tweet == other.tweet
+            && (self.arweave_tx_id.is_none() || self.arweave_tx_id == other.arweave_tx_id)
+    }
+}
@@ -0,0 +1,41 @@
+use alloc::vec::Vec;
+
+const NUMBER_SIZE: usize = 4;
+
+fn read_number(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset + NUMBER_SIZE)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+}
+
+// Split a molecule table or dynvec into its items.
+//
+// Both share the same header: total_size | offset_0 | ... | offset_n-1,
+// followed by the items themselves. Returns None if the header is malformed.
+pub fn split_dynamic(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let total_size = read_number(data, 0)?;
+    if total_size != data.len() {
+        return None;
+    }
+    if total_size == NUMBER_SIZE {
+        return Some(Vec::new());
+    }
+    let first_offset = read_number(data, NUMBER_SIZE)?;
+    if first_offset % NUMBER_SIZE != 0 || first_offset < NUMBER_SIZE * 2 || first_offset > total_size {
+        return None;
+    }
+    let count = first_offset / NUMBER_SIZE - 1;
+    let mut offsets = Vec::with_capacity(count + 1);
+    for i in 0..count {
+        offsets.push(read_number(data, NUMBER_SIZE * (i + 1))?);
+    }
+    offsets.push(total_size);
+
+    let mut items = Vec::with_capacity(count);
+    for window in offsets.windows(2) {
+        if window[0] > window[1] {
+            return None;
+        }
+        items.push(&data[window[0]..window[1]]);
+    }
+    Some(items)
+}
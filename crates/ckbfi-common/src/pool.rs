@@ -0,0 +1,156 @@
+// Unique liquidity cell data and pool governance.
+//
+// data: xudt_liquidity(16, little endian) | ckb_liquidity(16, little endian) | curve_ref(33) | issuance_closed(1) | governance(optional)
+// governance: status(1) | fee_bps(2, little endian) | admin_lock_hash(32)
+//
+// curve_ref is the bonding curve code the pool cells are locked with, it is
+// chosen at pool creation so the liquidity manager doesn't depend on the
+// curve code hash. The curve cells reference the pool back by the type script
+// hash of the unique liquidity cell.
+//
+// issuance_closed is 1 when the pool was created with proof that the xUDT
+// owner can't mint anymore, see the unique liquidity manager. It is written at
+// genesis and never changes.
+//
+// Pools created without governance have no admin, are always active and
+// charge no fee.
+//
+// Fee and admin changes are timelocked. The admin first creates a proposal
+// cell, typed by the unique liquidity manager with args
+// token | type_id | PROPOSAL_FLAG, and can only execute it by consuming the
+// proposal with a relative since of at least GOVERNANCE_DELAY.
+//
+// proposal data: fee_bps(2, little endian) | admin_lock_hash(32) | curve_ref(33, optional)
+//
+// A proposal with curve_ref moves the bonding curve cells of the pool to the
+// referenced curve code and updates curve_ref of the pool, this is how a pool
+// pinned to a curve data hash switches to the upgradable Type ID deployment.
+
+use crate::script_ref::{ScriptRef, SCRIPT_REF_LEN};
+
+pub const LIQUIDITY_LEN: usize = 16 + 16;
+pub const POOL_DATA_LEN: usize = LIQUIDITY_LEN + SCRIPT_REF_LEN + 1;
+pub const GOVERNANCE_LEN: usize = 1 + 2 + 32;
+pub const PROPOSAL_LEN: usize = 2 + 32;
+
+pub const POOL_STATUS_ACTIVE: u8 = 0;
+pub const POOL_STATUS_PAUSED: u8 = 1;
+// emergency withdrawal, every asset of the pool went to UTXOSwap
+pub const POOL_STATUS_GRADUATED: u8 = 2;
+
+// 10%
+pub const MAX_FEE_BPS: u16 = 1000;
+
+pub const PROPOSAL_FLAG: u8 = 1;
+// relative since timestamp in seconds, 24 hours
+pub const GOVERNANCE_DELAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolError {
+    InvalidData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolStatus {
+    Active,
+    Paused,
+    Graduated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Governance {
+    pub status: PoolStatus,
+    pub fee_bps: u16,
+    // lock script hash of the admin, a multisig lock works as well
+    pub admin_lock_hash: [u8; 32],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolData {
+    pub xudt_liquidity: u128,
+    pub ckb_liquidity: u128,
+    pub curve_ref: ScriptRef,
+    pub issuance_closed: bool,
+    pub governance: Option<Governance>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Proposal {
+    pub fee_bps: u16,
+    pub admin_lock_hash: [u8; 32],
+    pub curve_ref: Option<ScriptRef>,
+}
+
+fn parse_fee(data: &[u8]) -> Result<u16, PoolError> {
+    let fee_bps = u16::from_le_bytes(data.try_into().unwrap());
+    if fee_bps > MAX_FEE_BPS {
+        return Err(PoolError::InvalidData);
+    }
+    Ok(fee_bps)
+}
+
+impl PoolData {
+    pub fn parse(data: &[u8]) -> Result<Self, PoolError> {
+        if data.len() != POOL_DATA_LEN && data.len() != POOL_DATA_LEN + GOVERNANCE_LEN {
+            return Err(PoolError::InvalidData);
+        }
+        let xudt_liquidity = u128::from_le_bytes(data[..16].try_into().unwrap());
+        let ckb_liquidity = u128::from_le_bytes(data[16..32].try_into().unwrap());
+        let curve_ref = ScriptRef::parse(&data[LIQUIDITY_LEN..LIQUIDITY_LEN + SCRIPT_REF_LEN]).ok_or(PoolError::InvalidData)?;
+        let issuance_closed = match data[POOL_DATA_LEN - 1] {
+            0 => false,
+            1 => true,
+            _ => return Err(PoolError::InvalidData),
+        };
+        let governance = match data.get(POOL_DATA_LEN..) {
+            Some(gov) if !gov.is_empty() => {
+                let status = match gov[0] {
+                    POOL_STATUS_ACTIVE => PoolStatus::Active,
+                    POOL_STATUS_PAUSED => PoolStatus::Paused,
+                    POOL_STATUS_GRADUATED => PoolStatus::Graduated,
+                    _ => return Err(PoolError::InvalidData),
+                };
+                Some(Governance {
+                    status,
+                    fee_bps: parse_fee(&gov[1..3])?,
+                    admin_lock_hash: gov[3..].try_into().unwrap(),
+                })
+            }
+            _ => None,
+        };
+        Ok(PoolData {
+            xudt_liquidity,
+            ckb_liquidity,
+            curve_ref,
+            issuance_closed,
+            governance,
+        })
+    }
+
+    pub fn status(&self) -> PoolStatus {
+        self.governance
+            .map(|gov| gov.status)
+            .unwrap_or(PoolStatus::Active)
+    }
+
+    pub fn fee_bps(&self) -> u16 {
+        self.governance.map(|gov| gov.fee_bps).unwrap_or(0)
+    }
+}
+
+impl Proposal {
+    pub fn parse(data: &[u8]) -> Result<Self, PoolError> {
+        if data.len() != PROPOSAL_LEN && data.len() != PROPOSAL_LEN + SCRIPT_REF_LEN {
+            return Err(PoolError::InvalidData);
+        }
+        let curve_ref = match data.get(PROPOSAL_LEN..) {
+            Some(r) if !r.is_empty() => Some(ScriptRef::parse(r).ok_or(PoolError::InvalidData)?),
+            _ => None,
+        };
+        Ok(Proposal {
+            fee_bps: parse_fee(&data[..2])?,
+            admin_lock_hash: data[2..PROPOSAL_LEN].try_into().unwrap(),
+            curve_ref,
+        })
+    }
+}
@@ -0,0 +1,79 @@
+// Deployment registry cell.
+//
+// data: xudt(33) | utxoswap(33) | order(33) | bondings_curve(33) | unique_liquidity_manager(33)
+//
+// Every entry is a script_ref (code_hash | hash_type). Entries added later
+// are appended, contracts ignore the bytes they don't know.
+//
+// The registry cell uses the built-in Type ID script as its type script and
+// the registry admin lock (usually a multisig lock) as its lock, so there is
+// exactly one registry cell and only the admin can update it. Contracts find
+// it in cell_deps by REGISTRY_TYPE_HASH and have no built-in deployments:
+// a pool genesis, a migration and the UTXOSwap outputs of a launch or an
+// emergency withdrawal are only accepted with the registry in cell_deps.
+
+use crate::script_ref::{ScriptRef, SCRIPT_REF_LEN};
+
+pub const REGISTRY_ENTRIES: usize = 5;
+pub const REGISTRY_LEN: usize = REGISTRY_ENTRIES * SCRIPT_REF_LEN;
+
+// Set at build time from CKBFI_REGISTRY_TYPE_HASH (hex, optional 0x prefix),
+// the default in .cargo/config.toml is the registry of the tests.
+pub const REGISTRY_TYPE_HASH: [u8; 32] = parse_hash(env!("CKBFI_REGISTRY_TYPE_HASH"));
+
+const fn hex_digit(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => panic!("CKBFI_REGISTRY_TYPE_HASH is not hex"),
+    }
+}
+
+const fn parse_hash(hex: &str) -> [u8; 32] {
+    let hex = hex.as_bytes();
+    let start = if hex.len() > 2 && hex[0] == b'0' && hex[1] == b'x' { 2 } else { 0 };
+    if hex.len() - start != 64 {
+        panic!("CKBFI_REGISTRY_TYPE_HASH must be 32 bytes");
+    }
+    let mut hash = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        hash[i] = hex_digit(hex[start + 2 * i]) << 4 | hex_digit(hex[start + 2 * i + 1]);
+        i += 1;
+    }
+    hash
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistryError {
+    InvalidData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registry {
+    pub xudt: ScriptRef,
+    pub utxoswap: ScriptRef,
+    pub order: ScriptRef,
+    pub bondings_curve: ScriptRef,
+    pub unique_liquidity_manager: ScriptRef,
+}
+
+impl Registry {
+    pub fn parse(data: &[u8]) -> Result<Self, RegistryError> {
+        if data.len() < REGISTRY_LEN {
+            return Err(RegistryError::InvalidData);
+        }
+        let entry = |i: usize| {
+            ScriptRef::parse(&data[i * SCRIPT_REF_LEN..(i + 1) * SCRIPT_REF_LEN])
+                .ok_or(RegistryError::InvalidData)
+        };
+        Ok(Registry {
+            xudt: entry(0)?,
+            utxoswap: entry(1)?,
+            order: entry(2)?,
+            bondings_curve: entry(3)?,
+            unique_liquidity_manager: entry(4)?,
+        })
+    }
+}
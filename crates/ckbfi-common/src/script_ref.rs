@@ -0,0 +1,73 @@
+// Reference to another ckbfi contract.
+//
+// script_ref: code_hash(32) | hash_type(1)
+//
+// A contract deployed in a Type ID code cell is referenced with
+// hash_type = type and the Type ID script hash as code_hash, the reference
+// stays the same when the code cell is upgraded. A contract referenced by
+// data hash is pinned to one binary, data, data1 and data2 only pick the VM
+// version and are treated as the same reference.
+
+use ckb_std::ckb_types::{packed::Script, prelude::*};
+
+pub const SCRIPT_REF_LEN: usize = 32 + 1;
+
+pub const HASH_TYPE_DATA: u8 = 0;
+pub const HASH_TYPE_TYPE: u8 = 1;
+pub const HASH_TYPE_DATA1: u8 = 2;
+pub const HASH_TYPE_DATA2: u8 = 4;
+
+// built-in Type ID script, code_hash is "TYPE_ID" in ascii
+pub const TYPE_ID_REF: ScriptRef = ScriptRef::new(
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x54, 0x59, 0x50, 0x45, 0x5f, 0x49, 0x44,
+    ],
+    HASH_TYPE_TYPE,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptRef {
+    pub code_hash: [u8; 32],
+    pub hash_type: u8,
+}
+
+impl ScriptRef {
+    pub const fn new(code_hash: [u8; 32], hash_type: u8) -> Self {
+        ScriptRef {
+            code_hash,
+            hash_type,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() != SCRIPT_REF_LEN {
+            return None;
+        }
+        match data[32] {
+            HASH_TYPE_DATA | HASH_TYPE_TYPE | HASH_TYPE_DATA1 | HASH_TYPE_DATA2 => {
+                Some(ScriptRef::new(data[..32].try_into().unwrap(), data[32]))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn of(script: &Script) -> Self {
+        ScriptRef::new(script.code_hash().unpack(), u8::from(script.hash_type()))
+    }
+
+    // No code cell hashes to zero, a lock with a zero code_hash can never be
+    // unlocked.
+    pub fn is_unspendable(&self) -> bool {
+        self.code_hash == [0u8; 32]
+    }
+
+    pub fn is_type(&self) -> bool {
+        self.hash_type == HASH_TYPE_TYPE
+    }
+
+    // Whether script runs the referenced code.
+    pub fn matches(&self, script: &Script) -> bool {
+        let other = ScriptRef::of(script);
+        self.code_hash == other.code_hash && self.is_type() == other.is_type()
+    }
+}
@@ -0,0 +1,86 @@
+// Token standards a ckbfi pool can trade against CKB.
+//
+// token: standard(1) | type_hash(32)
+//
+// The traded token is identified by the hash of its full type script, so
+// code_hash, hash_type and args all take part in the comparison. The standard
+// decides how cell data is read. Only xUDT is checked against its deployment
+// (the registry entry) by the unique liquidity manager at pool genesis. sUDT
+// and custom tokens read cell data the same way and are never checked against
+// the token's type script, so for them the standard byte is advisory. All of
+// them keep the amount in the first 16 bytes of cell data (little endian).
+
+use crate::xudt::{self, XudtError};
+
+pub const TOKEN_STANDARD_XUDT: u8 = 0;
+pub const TOKEN_STANDARD_SUDT: u8 = 1;
+pub const TOKEN_STANDARD_CUSTOM: u8 = 2;
+
+pub const TOKEN_LEN: usize = 1 + 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    UnknownStandard,
+    InvalidArgs,
+    InvalidData,
+}
+
+impl From<XudtError> for TokenError {
+    fn from(err: XudtError) -> Self {
+        match err {
+            XudtError::InvalidArgs => Self::InvalidArgs,
+            XudtError::InvalidData => Self::InvalidData,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenStandard {
+    Xudt,
+    Sudt,
+    // any UDT whose code cell is referenced by type hash
+    Custom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub standard: TokenStandard,
+    // blake2b hash of the full token type script
+    pub type_hash: [u8; 32],
+}
+
+impl Token {
+    // Parse the token part of contract args.
+    pub fn parse(data: &[u8]) -> Result<Self, TokenError> {
+        if data.len() != TOKEN_LEN {
+            return Err(TokenError::InvalidArgs);
+        }
+        let standard = match data[0] {
+            TOKEN_STANDARD_XUDT => TokenStandard::Xudt,
+            TOKEN_STANDARD_SUDT => TokenStandard::Sudt,
+            TOKEN_STANDARD_CUSTOM => TokenStandard::Custom,
+            _ => return Err(TokenError::UnknownStandard),
+        };
+        Ok(Token {
+            standard,
+            type_hash: data[1..].try_into().unwrap(),
+        })
+    }
+
+    // Whether a cell with the given type script hash holds this token.
+    pub fn is_token(&self, type_hash: Option<[u8; 32]>) -> bool {
+        type_hash == Some(self.type_hash)
+    }
+
+    // Read the amount of a cell of this token.
+    pub fn parse_amount(&self, data: &[u8]) -> Result<u128, TokenError> {
+        match self.standard {
+            TokenStandard::Xudt => Ok(xudt::parse_amount(data)?),
+            // sUDT and other UDTs leave everything after the amount to the issuer
+            TokenStandard::Sudt | TokenStandard::Custom => {
+                let amount = data.get(..xudt::UDT_LEN).ok_or(TokenError::InvalidData)?;
+                Ok(u128::from_le_bytes(amount.try_into().unwrap()))
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// Structured debug traces of the contracts.
+//
+//     trace!("buy", sold_xudt = sold, required_ckb = required);
+//
+// prints `bondings-curve-contract buy sold_xudt=.. required_ckb=..` through
+// the debug syscall when the debug-log feature is enabled. Every contract
+// forwards its own debug-log feature here, `make build DEBUG_LOG=true` turns it
+// on. Without it the fields are only type checked and nothing ends up in the
+// binary, so release builds don't pay code size or cycles for the traces.
+
+use core::fmt;
+
+// Formats bytes as 0x prefixed hex, for hashes and args.
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl fmt::Debug for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("0x")?;
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "debug-log")]
+pub fn emit(contract: &str, event: &str, fields: &[(&str, &dyn fmt::Debug)]) {
+    use alloc::string::String;
+    use core::fmt::Write;
+
+    let mut line = String::new();
+    let _ = write!(line, "{} {}", contract, event);
+    for (key, value) in fields {
+        let _ = write!(line, " {}={:?}", key, value);
+    }
+    ckb_std::syscalls::debug(line);
+}
+
+#[cfg(feature = "debug-log")]
+#[macro_export]
+macro_rules! trace {
+    ($event:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        $crate::trace::emit(
+            env!("CARGO_PKG_NAME"),
+            $event,
+            &[$((stringify!($key), &$value as &dyn core::fmt::Debug)),*],
+        )
+    };
+}
+
+#[cfg(not(feature = "debug-log"))]
+#[macro_export]
+macro_rules! trace {
+    ($event:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        if false {
+            $(let _ = &$value;)*
+        }
+    };
+}
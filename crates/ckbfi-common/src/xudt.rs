@@ -0,0 +1,121 @@
+// xUDT cell layout, see
+// https://github.com/nervosnetwork/rfcs/blob/master/rfcs/0052-extensible-udt/0052-extensible-udt.md
+//
+// args: owner_lock_hash(32) | flags(4, optional) | extension data(optional)
+// data: amount(16, little endian) | XudtData(optional)
+
+use ckb_std::ckb_types::{
+    packed::{BytesReader, BytesVecReader, ScriptReader},
+    prelude::*,
+};
+
+use crate::molecule::split_dynamic;
+
+pub const UDT_LEN: usize = 16;
+pub const OWNER_LOCK_HASH_LEN: usize = 32;
+pub const FLAGS_LEN: usize = 4;
+pub const BLAKE160_LEN: usize = 20;
+
+// owner mode flags live in the highest 3 bits of flags
+pub const OWNER_MODE_INPUT_TYPE: u32 = 0x8000_0000;
+pub const OWNER_MODE_OUTPUT_TYPE: u32 = 0x4000_0000;
+pub const OWNER_MODE_INPUT_LOCK_NOT: u32 = 0x2000_0000;
+pub const OWNER_MODE_MASK: u32 = OWNER_MODE_INPUT_TYPE | OWNER_MODE_OUTPUT_TYPE | OWNER_MODE_INPUT_LOCK_NOT;
+
+// extension kinds live in the remaining bits
+const EXTENSION_NONE: u32 = 0;
+const EXTENSION_SCRIPTS: u32 = 1;
+const EXTENSION_SCRIPTS_HASH: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XudtError {
+    InvalidArgs,
+    InvalidData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extension<'a> {
+    None,
+    // molecule ScriptVec of extension scripts
+    Scripts(&'a [u8]),
+    // blake160 of the ScriptVec, the scripts themselves are in the witness
+    ScriptsHash(&'a [u8]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XudtArgs<'a> {
+    pub owner_lock_hash: &'a [u8],
+    pub flags: u32,
+    pub extension: Extension<'a>,
+}
+
+impl XudtArgs<'_> {
+    pub fn owner_mode_flags(&self) -> u32 {
+        self.flags & OWNER_MODE_MASK
+    }
+}
+
+// Parse and validate xUDT type script args.
+pub fn parse_args(args: &[u8]) -> Result<XudtArgs<'_>, XudtError> {
+    if args.len() < OWNER_LOCK_HASH_LEN {
+        return Err(XudtError::InvalidArgs);
+    }
+    let (owner_lock_hash, rest) = args.split_at(OWNER_LOCK_HASH_LEN);
+    if rest.is_empty() {
+        // sUDT compatible args without flags
+        return Ok(XudtArgs {
+            owner_lock_hash,
+            flags: 0,
+            extension: Extension::None,
+        });
+    }
+    if rest.len() < FLAGS_LEN {
+        return Err(XudtError::InvalidArgs);
+    }
+    let (flags, ext) = rest.split_at(FLAGS_LEN);
+    let flags = u32::from_le_bytes(flags.try_into().unwrap());
+    let extension = match flags & !OWNER_MODE_MASK {
+        EXTENSION_NONE if ext.is_empty() => Extension::None,
+        EXTENSION_SCRIPTS if is_script_vec(ext) => Extension::Scripts(ext),
+        EXTENSION_SCRIPTS_HASH if ext.len() == BLAKE160_LEN => Extension::ScriptsHash(ext),
+        _ => return Err(XudtError::InvalidArgs),
+    };
+    Ok(XudtArgs {
+        owner_lock_hash,
+        flags,
+        extension,
+    })
+}
+
+// Read the amount from the first 16 bytes of xUDT cell data. Anything after
+// the amount has to be a well formed XudtData table.
+pub fn parse_amount(data: &[u8]) -> Result<u128, XudtError> {
+    if data.len() < UDT_LEN {
+        return Err(XudtError::InvalidData);
+    }
+    let (amount, extra) = data.split_at(UDT_LEN);
+    if !extra.is_empty() && !is_xudt_data(extra) {
+        return Err(XudtError::InvalidData);
+    }
+    Ok(u128::from_le_bytes(amount.try_into().unwrap()))
+}
+
+fn is_script_vec(data: &[u8]) -> bool {
+    match split_dynamic(data) {
+        Some(items) => items
+            .iter()
+            .all(|item| ScriptReader::verify(item, false).is_ok()),
+        None => false,
+    }
+}
+
+// table XudtData { lock: Bytes, data: BytesVec }
+fn is_xudt_data(data: &[u8]) -> bool {
+    match split_dynamic(data) {
+        Some(fields) if fields.len() >= 2 => {
+            BytesReader::verify(fields[0], false).is_ok()
+                && BytesVecReader::verify(fields[1], false).is_ok()
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,197 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// Bonding curve math shared by bondings-curve-contract and off-chain services.
+//
+// All xudt amounts are in the smallest unit (8 decimals), all ckb amounts are
+// in shannons. The curve itself works on whole tokens, fractions of a token
+// are not priced. `supply` is the amount of xudt already sold by the curve
+// plus LAUNCH_XUDT_AMOUNT, derived from the xudt left in the pool cells.
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+// total xudt supply 731000000
+pub const TOTAL_XUDT_SUPPLY: u128 = 731_000_000 * XUDT_UNIT;
+
+// launch ckb amount 10w
+pub const LAUNCH_CKB_AMOUNT: u64 = 100_000 * 100_000_000;
+pub const LAUNCH_XUDT_AMOUNT: u128 = 200_000_000 * XUDT_UNIT;
+
+// the creator may buy at most 5% of the supply in the pool genesis transaction
+pub const MAX_CREATOR_BUY: u128 = TOTAL_XUDT_SUPPLY / 20;
+
+// 10^decimals
+pub const XUDT_UNIT: u128 = 100_000_000;
+
+// virtual supply added to the curve so the first tokens are not free
+const SUPPLY_OFFSET: u128 = 100_000_000;
+const DG: u128 = 114_500_000_000_000;
+
+const BPS: u128 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveError {
+    // arithmetic overflow or underflow, the amounts are out of the curve range
+    Overflow,
+    // the pool can't provide or absorb the requested amount
+    InsufficientLiquidity,
+    // the amount isn't a whole number of tokens
+    FractionalAmount,
+}
+
+impl core::fmt::Display for CurveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CurveError::Overflow => write!(f, "amount out of curve range"),
+            CurveError::InsufficientLiquidity => write!(f, "insufficient pool liquidity"),
+            CurveError::FractionalAmount => write!(f, "amount is not a whole number of tokens"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CurveError {}
+
+// (n - 1) * n * (2n - 1) / dg, the curve integral up to n whole tokens
+fn sum(n: u128) -> Result<u128, CurveError> {
+    let a = n.checked_sub(1).ok_or(CurveError::Overflow)?;
+    let c = n
+        .checked_mul(2)
+        .and_then(|v| v.checked_sub(1))
+        .ok_or(CurveError::Overflow)?;
+    a.checked_mul(n)
+        .and_then(|v| v.checked_mul(c))
+        .map(|v| v / DG)
+        .ok_or(CurveError::Overflow)
+}
+
+// Ckb cost of moving the supply from current_xudt_amount to
+// current_xudt_amount + xudt_amount. Fractions of a token would be traded for
+// free, so both amounts have to be whole tokens.
+// u128::is_multiple_of needs rust 1.87, newer than the contract toolchains.
+#[allow(clippy::manual_is_multiple_of)]
+pub fn get_price(current_xudt_amount: u128, xudt_amount: u128) -> Result<u128, CurveError> {
+    if current_xudt_amount % XUDT_UNIT != 0 || xudt_amount % XUDT_UNIT != 0 {
+        return Err(CurveError::FractionalAmount);
+    }
+    let current = current_xudt_amount / XUDT_UNIT + SUPPLY_OFFSET;
+    let target = current
+        .checked_add(xudt_amount / XUDT_UNIT)
+        .ok_or(CurveError::Overflow)?;
+    sum(target)?
+        .checked_sub(sum(current)?)
+        .ok_or(CurveError::Overflow)
+}
+
+pub fn get_buy_price(current_xudt_amount: u128, xudt_amount: u128) -> Result<u128, CurveError> {
+    get_price(current_xudt_amount, xudt_amount)
+}
+
+pub fn get_sell_price(current_xudt_amount: u128, xudt_amount: u128) -> Result<u128, CurveError> {
+    let current = current_xudt_amount
+        .checked_sub(xudt_amount)
+        .ok_or(CurveError::Overflow)?;
+    get_price(current, xudt_amount)
+}
+
+// Curve supply for a pool holding pool_xudt_amount.
+pub fn supply(pool_xudt_amount: u128) -> Result<u128, CurveError> {
+    (LAUNCH_XUDT_AMOUNT + TOTAL_XUDT_SUPPLY)
+        .checked_sub(pool_xudt_amount)
+        .ok_or(CurveError::Overflow)
+}
+
+// Ckb a buyer has to pay to take xudt_out out of the pool.
+pub fn quote_buy(pool_xudt_amount: u128, xudt_out: u128) -> Result<u128, CurveError> {
+    get_buy_price(supply(pool_xudt_amount)?, xudt_out)
+}
+
+// Ckb the pool pays at most for xudt_in put into the pool.
+pub fn quote_sell(pool_xudt_amount: u128, xudt_in: u128) -> Result<u128, CurveError> {
+    get_sell_price(supply(pool_xudt_amount)?, xudt_in)
+}
+
+// Largest xudt amount (whole tokens) a buyer gets for ckb_in. The pool never
+// sells below LAUNCH_XUDT_AMOUNT.
+pub fn xudt_for_ckb(pool_xudt_amount: u128, ckb_in: u128) -> Result<u128, CurveError> {
+    let max_tokens = pool_xudt_amount.saturating_sub(LAUNCH_XUDT_AMOUNT) / XUDT_UNIT;
+    let (mut lo, mut hi) = (0u128, max_tokens);
+    while lo < hi {
+        let mid = lo + (hi - lo).div_ceil(2);
+        if quote_buy(pool_xudt_amount, mid * XUDT_UNIT)? <= ckb_in {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    Ok(lo * XUDT_UNIT)
+}
+
+// Smallest xudt amount (whole tokens) a seller has to put in to receive
+// ckb_out from the pool.
+pub fn xudt_for_ckb_out(pool_xudt_amount: u128, ckb_out: u128) -> Result<u128, CurveError> {
+    let max_tokens = TOTAL_XUDT_SUPPLY.saturating_sub(pool_xudt_amount) / XUDT_UNIT;
+    if quote_sell(pool_xudt_amount, max_tokens * XUDT_UNIT)? < ckb_out {
+        return Err(CurveError::InsufficientLiquidity);
+    }
+    let (mut lo, mut hi) = (0u128, max_tokens);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if quote_sell(pool_xudt_amount, mid * XUDT_UNIT)? >= ckb_out {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Ok(lo * XUDT_UNIT)
+}
+
+// Ckb a buyer pays for a curve cost of ckb, the fee stays in the pool.
+pub fn with_buy_fee(ckb: u128, fee_bps: u16) -> Result<u128, CurveError> {
+    ckb.checked_mul(u128::from(fee_bps))
+        .map(|fee| fee / BPS)
+        .and_then(|fee| ckb.checked_add(fee))
+        .ok_or(CurveError::Overflow)
+}
+
+// Curve cost covered by a buyer paying ckb including the fee.
+pub fn without_buy_fee(ckb: u128, fee_bps: u16) -> Result<u128, CurveError> {
+    ckb.checked_mul(BPS)
+        .map(|v| v / (BPS + u128::from(fee_bps)))
+        .ok_or(CurveError::Overflow)
+}
+
+// Ckb a seller receives for a curve value of ckb, the fee stays in the pool.
+pub fn with_sell_fee(ckb: u128, fee_bps: u16) -> Result<u128, CurveError> {
+    ckb.checked_mul(u128::from(fee_bps))
+        .and_then(|fee| ckb.checked_sub(fee / BPS))
+        .ok_or(CurveError::Overflow)
+}
+
+// Ckb the creator pays into the pool, fee included, to buy xudt_out in the
+// pool genesis transaction. The pool starts with TOTAL_XUDT_SUPPLY, so this is
+// the price of the very first xudt_out.
+pub fn creator_buy_cost(xudt_out: u128, fee_bps: u16) -> Result<u128, CurveError> {
+    if xudt_out > MAX_CREATOR_BUY {
+        return Err(CurveError::InsufficientLiquidity);
+    }
+    with_buy_fee(quote_buy(TOTAL_XUDT_SUPPLY, xudt_out)?, fee_bps)
+}
+
+// Ckb price of the next whole token.
+pub fn spot_price(pool_xudt_amount: u128) -> Result<u128, CurveError> {
+    quote_buy(pool_xudt_amount, XUDT_UNIT)
+}
+
+// How much more than spot price a buyer of xudt_out pays on average, in bps.
+pub fn price_impact_bps(pool_xudt_amount: u128, xudt_out: u128) -> Result<u128, CurveError> {
+    let tokens = xudt_out / XUDT_UNIT;
+    let spot_cost = spot_price(pool_xudt_amount)?
+        .checked_mul(tokens)
+        .ok_or(CurveError::Overflow)?;
+    if spot_cost == 0 {
+        return Ok(0);
+    }
+    let cost = quote_buy(pool_xudt_amount, xudt_out)?;
+    Ok(cost.saturating_sub(spot_cost) * BPS / spot_cost)
+}
@@ -0,0 +1,53 @@
+// wasm-bindgen exports for the frontend.
+//
+// Amounts cross the JS boundary as decimal strings since u128 doesn't fit in
+// a JS number.
+
+use wasm_bindgen::prelude::*;
+
+use crate::CurveError;
+
+fn parse(value: &str) -> Result<u128, JsError> {
+    value
+        .parse()
+        .map_err(|_| JsError::new(&format!("invalid amount: {}", value)))
+}
+
+fn format(value: Result<u128, CurveError>) -> Result<String, JsError> {
+    value.map(|v| v.to_string()).map_err(|e| JsError::new(&e.to_string()))
+}
+
+#[wasm_bindgen(js_name = quoteBuy)]
+pub fn quote_buy(pool_xudt_amount: &str, xudt_out: &str) -> Result<String, JsError> {
+    format(crate::quote_buy(parse(pool_xudt_amount)?, parse(xudt_out)?))
+}
+
+#[wasm_bindgen(js_name = quoteSell)]
+pub fn quote_sell(pool_xudt_amount: &str, xudt_in: &str) -> Result<String, JsError> {
+    format(crate::quote_sell(parse(pool_xudt_amount)?, parse(xudt_in)?))
+}
+
+#[wasm_bindgen(js_name = xudtForCkb)]
+pub fn xudt_for_ckb(pool_xudt_amount: &str, ckb_in: &str) -> Result<String, JsError> {
+    format(crate::xudt_for_ckb(parse(pool_xudt_amount)?, parse(ckb_in)?))
+}
+
+#[wasm_bindgen(js_name = xudtForCkbOut)]
+pub fn xudt_for_ckb_out(pool_xudt_amount: &str, ckb_out: &str) -> Result<String, JsError> {
+    format(crate::xudt_for_ckb_out(parse(pool_xudt_amount)?, parse(ckb_out)?))
+}
+
+#[wasm_bindgen(js_name = spotPrice)]
+pub fn spot_price(pool_xudt_amount: &str) -> Result<String, JsError> {
+    format(crate::spot_price(parse(pool_xudt_amount)?))
+}
+
+#[wasm_bindgen(js_name = priceImpactBps)]
+pub fn price_impact_bps(pool_xudt_amount: &str, xudt_out: &str) -> Result<String, JsError> {
+    format(crate::price_impact_bps(parse(pool_xudt_amount)?, parse(xudt_out)?))
+}
+
+#[wasm_bindgen(js_name = creatorBuyCost)]
+pub fn creator_buy_cost(xudt_out: &str, fee_bps: u16) -> Result<String, JsError> {
+    format(crate::creator_buy_cost(parse(xudt_out)?, fee_bps))
+}
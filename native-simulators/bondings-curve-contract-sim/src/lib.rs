@@ -0,0 +1,16 @@
+// Native build of bondings-curve-contract, ckb-testtool runs it instead of the
+// RISC-V binary when the tests are built with --features native-simulator.
+
+// ckb-testtool drops the exit code of native scripts, the first non-zero one
+// of a transaction is passed to the tests in this variable instead.
+const EXIT_CODE_VAR: &str = "CKBFI_SIM_EXIT_CODE";
+
+fn program_entry() -> i8 {
+    let code = bondings_curve_contract::program_entry();
+    if code != 0 && std::env::var_os(EXIT_CODE_VAR).is_none() {
+        std::env::set_var(EXIT_CODE_VAR, format!("bondings-curve-contract {}", code));
+    }
+    code
+}
+
+ckb_std::entry_simulator!(program_entry);
@@ -0,0 +1,186 @@
+// The reference aggregator against the local chain stand-in: it finds the
+// orders itself, and the transactions it builds have to pass the contracts.
+
+use crate::builder::*;
+use crate::chain::LocalChain;
+use ckb_testtool::ckb_types::{bytes::Bytes, packed::*, prelude::*};
+use ckbfi_aggregator::{Aggregator, Config, Error, Pool, Side, SkipReason};
+use ckbfi_curve::XUDT_UNIT;
+
+const POOL_XUDT: u128 = 500_000_000 * XUDT_UNIT;
+const POOL_CKB: u64 = 1_000_000 * 100_000_000;
+const BUY_AMOUNT: u128 = 10_000_000 * XUDT_UNIT;
+const BUY_CKB: u64 = 500 * 100_000_000;
+const SELL_AMOUNT: u128 = 4_000_000 * XUDT_UNIT;
+const FEE_BPS: u16 = 100;
+// 5%, above the pool fee and the price moving between the orders of a batch
+const SLIP_POINT_AFTER_FEE: u16 = 500;
+const TX_FEE: u64 = 100_000;
+
+fn live_pool() -> PoolFixture {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_ACTIVE, FEE_BPS);
+    pool.open(POOL_XUDT, POOL_CKB);
+    // pays the transaction fees
+    pool.deployment
+        .input(cell(&pool.aggregator_lock, None, CELL_CAPACITY), Bytes::new());
+    pool
+}
+
+fn aggregator(pool: &mut PoolFixture) -> Aggregator {
+    let deployment = &pool.deployment;
+    let cell_deps = [
+        &deployment.curve_out_point,
+        &deployment.manager_out_point,
+        &deployment.order_out_point,
+        &deployment.xudt_out_point,
+        &deployment.always_success,
+    ]
+    .into_iter()
+    .chain(&deployment.registry)
+    .map(|out_point| CellDep::new_builder().out_point(out_point.clone()).build())
+    .collect();
+    Aggregator::new(Config {
+        registry: registry_type(),
+        cell_deps,
+        lock: pool.aggregator_lock.clone(),
+        tx_fee: TX_FEE,
+    })
+}
+
+// A user with a cell, so the chain knows their lock.
+fn wallet(pool: &mut PoolFixture, name: &str) -> Script {
+    let user = pool.user(name);
+    pool.deployment.input(cell(&user, None, CELL_CAPACITY), Bytes::new());
+    user
+}
+
+// xudt and plain capacity held by lock
+fn balance(chain: &LocalChain, lock: &Script) -> (u128, u64) {
+    let (mut xudt, mut ckb) = (0, 0);
+    for (output, data) in chain.context.cells.values() {
+        if &output.lock() != lock {
+            continue;
+        }
+        match output.type_().to_opt() {
+            Some(_) => xudt += u128::from_le_bytes(data[..16].try_into().unwrap()),
+            None => ckb += Unpack::<u64>::unpack(&output.capacity()),
+        }
+    }
+    (xudt, ckb)
+}
+
+#[test]
+fn test_aggregator_fills_orders() {
+    let mut pool = live_pool();
+    let aggregator = aggregator(&mut pool);
+    let alice = wallet(&mut pool, "alice");
+    let bob = wallet(&mut pool, "bob");
+    let carol = wallet(&mut pool, "carol");
+    pool.buy_order(&alice, BUY_AMOUNT, SLIP_POINT_AFTER_FEE);
+    pool.buy_exact_in_order(&bob, BUY_CKB, SLIP_POINT_AFTER_FEE);
+    pool.sell_order(&carol, SELL_AMOUNT);
+    let mut chain = LocalChain::new(&mut pool.deployment.context);
+
+    // the buys go first, the sell waits for the next round
+    let round = aggregator.run(&chain).expect("round");
+    assert_eq!(round.len(), 1);
+    let (buys, tx) = &round[0];
+    assert_eq!(buys.side, Side::Buy);
+    assert_eq!(buys.fills.len(), 2);
+    assert!(buys.skipped.is_empty());
+    chain.submit(tx).expect("pass verification");
+
+    let round = aggregator.run(&chain).expect("round");
+    assert_eq!(round.len(), 1);
+    let (sells, tx) = &round[0];
+    assert_eq!(sells.side, Side::Sell);
+    assert_eq!(sells.fills.len(), 1);
+    chain.submit(tx).expect("pass verification");
+
+    assert!(aggregator.run(&chain).expect("round").is_empty());
+    assert_eq!(balance(&chain, &alice).0, BUY_AMOUNT);
+    let bob_xudt = balance(&chain, &bob).0;
+    assert!(bob_xudt > 0);
+    assert_eq!(balance(&chain, &carol), (0, CELL_CAPACITY * 2 + sells.fills[0].ckb));
+
+    let pools = Pool::find_all(&chain, &pool.unique_type).expect("pools");
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].xudt_reserve(), POOL_XUDT - BUY_AMOUNT - bob_xudt + SELL_AMOUNT);
+    assert_eq!(pools[0].ckb_reserve(), sells.ckb_reserve);
+}
+
+#[test]
+fn test_aggregator_skips_unfillable_orders() {
+    let mut pool = live_pool();
+    let aggregator = aggregator(&mut pool);
+    let alice = wallet(&mut pool, "alice");
+    // no room for alice's xudt cell, she gets less than she asks for
+    let lock = pool.order_lock(&alice, 0, BUY_AMOUNT, None);
+    let capacity = pool.buy_cost(BUY_AMOUNT);
+    let order = pool.deployment.input(cell(&lock, None, capacity), Bytes::new());
+    // the chain has never seen dave's lock
+    let dave = pool.user("dave");
+    let unknown = pool.buy_order(&dave, BUY_AMOUNT, SLIP_POINT_AFTER_FEE);
+    let chain = LocalChain::new(&mut pool.deployment.context);
+
+    assert!(aggregator.run(&chain).expect("round").is_empty());
+    let batches = aggregator.plan(&chain).expect("plan");
+    assert_eq!(batches.len(), 1);
+    assert!(batches[0].fills.is_empty());
+    let mut skipped = batches[0].skipped.clone();
+    skipped.sort_by_key(|(_, reason)| *reason == SkipReason::UnknownUser);
+    assert_eq!(
+        skipped,
+        vec![
+            (order.previous_output(), SkipReason::Slippage),
+            (unknown.input.previous_output(), SkipReason::UnknownUser),
+        ]
+    );
+    // both stay live for a later round
+    assert!(chain.live_cell(&order.previous_output()).is_some());
+    assert!(chain.live_cell(&unknown.input.previous_output()).is_some());
+}
+
+// alice's orders have different args, one order group per transaction
+#[test]
+fn test_aggregator_defers_other_order_group() {
+    let mut pool = live_pool();
+    let aggregator = aggregator(&mut pool);
+    let alice = wallet(&mut pool, "alice");
+    let first = pool.buy_order(&alice, BUY_AMOUNT, SLIP_POINT_AFTER_FEE);
+    let second = pool.buy_order(&alice, BUY_AMOUNT, SLIP_POINT_AFTER_FEE + 1);
+    let mut chain = LocalChain::new(&mut pool.deployment.context);
+
+    let round = aggregator.run(&chain).expect("round");
+    assert_eq!(round.len(), 1);
+    let (batch, tx) = &round[0];
+    assert_eq!(batch.fills.len(), 1);
+    let filled = batch.fills[0].order.cell.out_point.clone();
+    let deferred = [first.input.previous_output(), second.input.previous_output()]
+        .into_iter()
+        .find(|out_point| out_point != &filled)
+        .unwrap();
+    assert_eq!(batch.skipped, vec![(deferred.clone(), SkipReason::OtherOrderGroup)]);
+    chain.submit(tx).expect("pass verification");
+
+    let round = aggregator.run(&chain).expect("round");
+    assert_eq!(round.len(), 1);
+    let (batch, tx) = &round[0];
+    assert_eq!(batch.fills.len(), 1);
+    assert_eq!(batch.fills[0].order.cell.out_point, deferred);
+    chain.submit(tx).expect("pass verification");
+    assert_eq!(balance(&chain, &alice).0, BUY_AMOUNT * 2);
+}
+
+// the order and manager deployments come from the registry cell
+#[test]
+fn test_aggregator_without_registry() {
+    let mut pool = live_pool();
+    let mut aggregator = aggregator(&mut pool);
+    aggregator.config.registry = type_id_script(&[9; 32]);
+    let alice = wallet(&mut pool, "alice");
+    pool.buy_order(&alice, BUY_AMOUNT, SLIP_POINT_AFTER_FEE);
+    let chain = LocalChain::new(&mut pool.deployment.context);
+
+    assert_eq!(aggregator.plan(&chain).unwrap_err(), Error::RegistryMissing);
+}
@@ -0,0 +1,65 @@
+// Writes the xUDT binary from a get_live_cell response to the given path.
+//
+//     curl -d '{"id":1,"jsonrpc":"2.0","method":"get_live_cell","params":[<out point>,true]}' <rpc> \
+//         | cargo run -p tests --bin fetch_xudt -- tests/bins/xudt_rce
+//
+// `make prepare` runs it against the testnet deployment. The cell must be live,
+// its type script hash must be the xUDT code hash and its data must match the
+// data hash.
+
+use ckb_jsonrpc_types::CellWithStatus;
+use ckb_testtool::{
+    ckb_hash::blake2b_256,
+    ckb_types::{packed::Script, prelude::*},
+};
+use std::io::Read;
+use std::process::exit;
+use tests::replay::hex;
+
+// xUDT code hash (hash_type type) on testnet
+const XUDT_CODE_HASH: [u8; 32] = [
+    0x25, 0xc2, 0x9d, 0xc3, 0x17, 0x81, 0x1a, 0x6f, 0x6f, 0x39, 0x85, 0xa7, 0xa9, 0xeb, 0xc4, 0x83, 0x8b, 0xd3, 0x88,
+    0xd1, 0x9d, 0x0f, 0xee, 0xec, 0xf0, 0xbc, 0xd6, 0x0f, 0x6c, 0x09, 0x75, 0xbb,
+];
+
+fn fail(message: String) -> ! {
+    eprintln!("fetch_xudt: {}", message);
+    exit(1);
+}
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("usage: fetch_xudt <output path> < get_live_cell response");
+        exit(2);
+    });
+    let mut response = String::new();
+    std::io::stdin()
+        .read_to_string(&mut response)
+        .unwrap_or_else(|err| fail(format!("read response: {}", err)));
+    let mut response: serde_json::Value =
+        serde_json::from_str(&response).unwrap_or_else(|err| fail(format!("parse response: {}", err)));
+    if let Some(error) = response.get("error") {
+        fail(format!("rpc error: {}", error));
+    }
+    let cell: CellWithStatus = serde_json::from_value(response["result"].take())
+        .unwrap_or_else(|err| fail(format!("parse cell: {}", err)));
+    if cell.status != "live" {
+        fail(format!("cell is {}", cell.status));
+    }
+    let cell = cell.cell.unwrap_or_else(|| fail("no cell in response".to_string()));
+    let type_script: Script = cell
+        .output
+        .type_
+        .unwrap_or_else(|| fail("cell has no type script".to_string()))
+        .into();
+    if type_script.calc_script_hash().as_slice() != XUDT_CODE_HASH {
+        fail(format!("type script hash {} is not xUDT", hex(&type_script.calc_script_hash())));
+    }
+    let data = cell.data.unwrap_or_else(|| fail("no data in response".to_string()));
+    let binary = data.content.into_bytes();
+    if blake2b_256(&binary) != data.hash.0 {
+        fail("data doesn't match its hash".to_string());
+    }
+    std::fs::write(&path, &binary).unwrap_or_else(|err| fail(format!("write {}: {}", path, err)));
+    println!("{}: {} bytes, data hash {:#x}", path, binary.len(), data.hash);
+}
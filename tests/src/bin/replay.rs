@@ -0,0 +1,74 @@
+// Re-runs a dumped mock transaction against the current build.
+//
+//     cargo run -p tests --bin replay -- failed_txs/0x<hash>.json [--contract <name>=0x<code hash>]...
+//
+// The contracts are named by failed_txs/0x<hash>.contracts.json when it
+// exists, --contract adds or overrides names, e.g. for a mainnet or testnet
+// transaction exported to the same mock format.
+
+use ckb_mock_tx_types::{MockTransaction, ReprMockTransaction};
+use ckb_testtool::ckb_script::ScriptError;
+use ckbfi_common::error::decode;
+use std::path::PathBuf;
+use std::process::exit;
+use tests::replay::{describe, replay, ContractNames};
+
+fn usage() -> ! {
+    eprintln!("usage: replay <mock tx json> [--contract <name>=0x<code hash>]...");
+    exit(2);
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let path = PathBuf::from(args.next().unwrap_or_else(|| usage()));
+    let json = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("read {:?}: {}", path, err);
+        exit(2);
+    });
+    let repr: ReprMockTransaction = serde_json::from_str(&json).unwrap_or_else(|err| {
+        eprintln!("parse {:?}: {}", path, err);
+        exit(2);
+    });
+    let mock_tx: MockTransaction = repr.into();
+
+    let names_path = path.with_extension("contracts.json");
+    let mut names: ContractNames = match std::fs::read(&names_path) {
+        Ok(data) => serde_json::from_slice(&data).expect("contract names"),
+        Err(_) => ContractNames::new(),
+    };
+    while let Some(arg) = args.next() {
+        if arg != "--contract" {
+            usage();
+        }
+        let value = args.next().unwrap_or_else(|| usage());
+        let (name, code_hash) = value.split_once('=').unwrap_or_else(|| usage());
+        names.insert(code_hash.to_lowercase(), name.to_string());
+    }
+
+    let results = replay(mock_tx, &names).unwrap_or_else(|err| {
+        eprintln!("resolve transaction: {}", err);
+        exit(2);
+    });
+    let mut failed = false;
+    for script in results {
+        let contract = script.contract.as_deref().unwrap_or("unknown");
+        println!("{}: {}", contract, describe(&script.group));
+        match script.result {
+            Ok(cycles) => println!("    ok, {} cycles", cycles),
+            Err(err) => {
+                failed = true;
+                println!("    {}", err);
+                if let ScriptError::ValidationFailure(_, exit_code) = err {
+                    // exit codes are unique across the ckbfi contracts, so this
+                    // also names the contract of unnamed scripts
+                    if let Some(error) = decode(exit_code) {
+                        println!("    exit code {}: {}::Error::{}", exit_code, error.contract, error.name);
+                    }
+                }
+            }
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
@@ -0,0 +1,760 @@
+// Transaction builder for the transaction shapes in the README.
+//
+// PoolFixture deploys every contract in a Context and tracks the live cells of
+// one pool. Its methods describe what happens to the pool (create_pool, buy,
+// sell, graduate) and return the TransactionView doing it. After verifying a
+// transaction, commit makes its outputs live, so the next transaction spends
+// them. Users place orders as OrderFixtures, which the pool fills or the user
+// cancels later.
+//
+// Invalid variants of a transaction are built from the valid one with
+// replace_output, remove_output and PoolFixture::set_reserves.
+
+use ckb_testtool::{
+    ckb_error::Error,
+    ckb_hash::new_blake2b,
+    ckb_types::{
+        bytes::Bytes,
+        core::{Cycle, ScriptHashType, TransactionBuilder, TransactionView},
+        packed::*,
+        prelude::*,
+    },
+    context::Context,
+};
+use ckbfi_common::{
+    pool::{GOVERNANCE_DELAY, PROPOSAL_FLAG},
+    script_ref::TYPE_ID_REF,
+    xudt::{OWNER_MODE_INPUT_LOCK_NOT, OWNER_MODE_INPUT_TYPE},
+};
+use ckbfi_curve::{quote_buy, quote_sell, with_buy_fee, with_sell_fee, xudt_for_ckb, TOTAL_XUDT_SUPPLY};
+
+use crate::{deploy_contract, verify_tx, Loader};
+
+pub const MAX_CYCLES: u64 = 70_000_000;
+pub const CELL_CAPACITY: u64 = 1000 * 100_000_000;
+
+pub use ckbfi_common::{
+    metadata::METADATA_FLAG,
+    pool::{POOL_STATUS_ACTIVE, POOL_STATUS_GRADUATED, POOL_STATUS_PAUSED},
+    token::{TOKEN_STANDARD_CUSTOM, TOKEN_STANDARD_XUDT},
+};
+
+// The contracts take UTXOSwap from the registry, the registry of the tests
+// lists this code hash (the testnet deployment).
+pub const UTXOSWAP_CODE_HASH: [u8; 32] = [
+    0x4e, 0x9c, 0x30, 0xc8, 0xd6, 0xce, 0x27, 0x57, 0x40, 0xfb, 0xe6, 0x9e, 0xae, 0x49, 0xc3, 0xd8, 0xc2, 0x13, 0x57,
+    0x8c, 0x5b, 0xd0, 0x66, 0xf4, 0x93, 0x8f, 0xe3, 0xc7, 0xde, 0xc6, 0xe1, 0x01,
+];
+
+// output index of the unique cell in the pool genesis transaction
+const UNIQUE_CELL_INDEX: u64 = 2;
+
+// standard | token type hash
+pub fn token_bytes(standard: u8, token_type: &Script) -> Vec<u8> {
+    let mut token = vec![standard];
+    token.extend_from_slice(token_type.calc_script_hash().as_slice());
+    token
+}
+
+// xudt_liquidity | ckb_liquidity | curve_ref | issuance_closed
+pub fn pool_data(xudt_liquidity: u128, ckb_liquidity: u64, curve_lock: &Script, issuance_closed: bool) -> Bytes {
+    let mut data = xudt_liquidity.to_le_bytes().to_vec();
+    data.extend_from_slice(&(ckb_liquidity as u128).to_le_bytes());
+    data.extend_from_slice(&script_ref(curve_lock));
+    data.push(issuance_closed.into());
+    data.into()
+}
+
+pub fn udt_data(amount: u128) -> Bytes {
+    Bytes::from(amount.to_le_bytes().to_vec())
+}
+
+pub fn cell(lock: &Script, type_: Option<&Script>, capacity: u64) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(capacity.pack())
+        .lock(lock.clone())
+        .type_(type_.cloned().pack())
+        .build()
+}
+
+// Type ID of the cell created at output_index by a transaction spending first_input first.
+pub fn type_id(first_input: &CellInput, output_index: u64) -> [u8; 32] {
+    let mut blake2b = new_blake2b();
+    blake2b.update(first_input.as_slice());
+    blake2b.update(&output_index.to_le_bytes());
+    let mut ret = [0; 32];
+    blake2b.finalize(&mut ret);
+    ret
+}
+
+fn molecule_bytes(raw: &[u8]) -> Vec<u8> {
+    let mut data = (raw.len() as u32).to_le_bytes().to_vec();
+    data.extend_from_slice(raw);
+    data
+}
+
+// TokenMetadata { name, symbol, decimals, tweet, arweave_tx_id }
+pub fn metadata_data(arweave_tx_id: &[u8]) -> Bytes {
+    let fields = [
+        molecule_bytes(b"Ckbfi Meme"),
+        molecule_bytes(b"MEME"),
+        vec![8],
+        molecule_bytes(b"https://x.com/ckbfi/status/1"),
+        molecule_bytes(arweave_tx_id),
+    ];
+    let header_len = 4 * (fields.len() + 1);
+    let total_len = header_len + fields.iter().map(Vec::len).sum::<usize>();
+    let mut data = (total_len as u32).to_le_bytes().to_vec();
+    let mut offset = header_len;
+    for field in &fields {
+        data.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    for field in &fields {
+        data.extend_from_slice(field);
+    }
+    data.into()
+}
+
+// Type ID args of the registry cell the tests create, the default
+// CKBFI_REGISTRY_TYPE_HASH in .cargo/config.toml is the hash of registry_type.
+pub const REGISTRY_ARGS: [u8; 32] = [1; 32];
+
+pub fn registry_type() -> Script {
+    type_id_script(&REGISTRY_ARGS)
+}
+
+// Type ID args of the cell closing the issuance of xUDT pools
+pub const ISSUANCE_OWNER_ARGS: [u8; 32] = [2; 32];
+
+pub fn type_id_script(args: &[u8; 32]) -> Script {
+    Script::new_builder()
+        .code_hash(TYPE_ID_REF.code_hash.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(args.to_vec()).pack())
+        .build()
+}
+
+// code_hash | hash_type
+pub fn script_ref(script: &Script) -> Vec<u8> {
+    let mut data = script.code_hash().as_slice().to_vec();
+    data.push(script.hash_type().into());
+    data
+}
+
+// xudt | utxoswap | order | bondings_curve | unique_liquidity_manager
+pub fn registry_data(xudt: &Script, utxoswap: &Script, order: &Script, bondings_curve: &Script, manager: &Script) -> Bytes {
+    [xudt, utxoswap, order, bondings_curve, manager]
+        .into_iter()
+        .flat_map(script_ref)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+// Output locks don't run, so a lock with the UTXOSwap code hash stands in for
+// the UTXOSwap pool.
+pub fn utxoswap_lock() -> Script {
+    Script::new_builder()
+        .code_hash(UTXOSWAP_CODE_HASH.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .build()
+}
+
+// tx with outputs[index] replaced
+pub fn replace_output(tx: &TransactionView, index: usize, output: CellOutput, data: Bytes) -> TransactionView {
+    let mut outputs: Vec<_> = tx.outputs().into_iter().collect();
+    let mut outputs_data: Vec<_> = tx.outputs_data().into_iter().collect();
+    outputs[index] = output;
+    outputs_data[index] = data.pack();
+    tx.as_advanced_builder()
+        .set_outputs(outputs)
+        .set_outputs_data(outputs_data)
+        .build()
+}
+
+// tx without outputs[index]
+pub fn remove_output(tx: &TransactionView, index: usize) -> TransactionView {
+    let mut outputs: Vec<_> = tx.outputs().into_iter().collect();
+    let mut outputs_data: Vec<_> = tx.outputs_data().into_iter().collect();
+    outputs.remove(index);
+    outputs_data.remove(index);
+    tx.as_advanced_builder()
+        .set_outputs(outputs)
+        .set_outputs_data(outputs_data)
+        .build()
+}
+
+// All ckbfi contracts and xUDT deployed in one context, with a registry cell
+// listing them. Issuers, users, the aggregator and the admin unlock their
+// cells with always success locks.
+pub struct Deployment {
+    pub context: Context,
+    pub curve_out_point: OutPoint,
+    pub manager_out_point: OutPoint,
+    pub order_out_point: OutPoint,
+    pub xudt_out_point: OutPoint,
+    pub always_success: OutPoint,
+    // registry cell, in the cell_deps of every transaction built
+    pub registry: Option<OutPoint>,
+}
+
+impl Default for Deployment {
+    fn default() -> Self {
+        let mut context = Context::default();
+        let curve_out_point = deploy_contract(&mut context, "bondings-curve-contract");
+        let manager_out_point = deploy_contract(&mut context, "unique_liquidity_manager");
+        let order_out_point = deploy_contract(&mut context, "order-contract");
+        let xudt_out_point = context.deploy_cell(Loader::bundled().load_binary("xudt_rce"));
+        let always_success = context.deploy_cell(ckb_testtool::builtin::ALWAYS_SUCCESS.clone());
+        let mut deployment = Deployment {
+            context,
+            curve_out_point,
+            manager_out_point,
+            order_out_point,
+            xudt_out_point,
+            always_success,
+            registry: None,
+        };
+        let registry = deployment.registry_data();
+        deployment.set_registry(Some(registry));
+        deployment
+    }
+}
+
+impl Deployment {
+    pub fn lock(&mut self, name: &str) -> Script {
+        self.context
+            .build_script(&self.always_success, Bytes::from(name.as_bytes().to_vec()))
+            .expect("script")
+    }
+
+    // xUDT args without flags, an input locked by owner_lock unlocks the owner mode
+    pub fn xudt(&mut self, owner_lock: &Script) -> Script {
+        self.context
+            .build_script(&self.xudt_out_point, owner_lock.calc_script_hash().as_bytes())
+            .expect("script")
+    }
+
+    // xUDT args with owner mode input type | input lock not, only a
+    // transaction consuming the cell typed owner_type mints
+    pub fn owned_xudt(&mut self, owner_type: &Script) -> Script {
+        let mut args = owner_type.calc_script_hash().as_slice().to_vec();
+        args.extend_from_slice(&(OWNER_MODE_INPUT_TYPE | OWNER_MODE_INPUT_LOCK_NOT).to_le_bytes());
+        self.context
+            .build_script(&self.xudt_out_point, args.into())
+            .expect("script")
+    }
+
+    // A token of another UDT implementation, its type script always succeeds
+    pub fn custom_token(&mut self, owner_lock: &Script) -> Script {
+        self.context
+            .build_script(&self.always_success, owner_lock.calc_script_hash().as_bytes())
+            .expect("script")
+    }
+
+    // registry listing the contracts of this deployment
+    pub fn registry_data(&mut self) -> Bytes {
+        let xudt = self.xudt(&Script::default());
+        let order = self.context.build_script(&self.order_out_point, Bytes::new()).expect("script");
+        let curve = self.context.build_script(&self.curve_out_point, Bytes::new()).expect("script");
+        let manager = self.context.build_script(&self.manager_out_point, Bytes::new()).expect("script");
+        registry_data(&xudt, &utxoswap_lock(), &order, &curve, &manager)
+    }
+
+    // Replaces the registry cell, transactions built from now on reference
+    // the new one, or none.
+    pub fn set_registry(&mut self, data: Option<Bytes>) {
+        self.registry = data.map(|data| {
+            let lock = self.lock("registry admin");
+            self.context
+                .create_cell(cell(&lock, Some(&registry_type()), CELL_CAPACITY), data)
+        });
+    }
+
+    pub fn input(&mut self, output: CellOutput, data: Bytes) -> CellInput {
+        CellInput::new_builder()
+            .previous_output(self.context.create_cell(output, data))
+            .build()
+    }
+
+    pub fn build_tx(&mut self, inputs: Vec<CellInput>, outputs: Vec<(CellOutput, Bytes)>) -> TransactionView {
+        let (outputs, outputs_data): (Vec<_>, Vec<_>) = outputs.into_iter().unzip();
+        let tx = TransactionBuilder::default()
+            .inputs(inputs)
+            .outputs(outputs)
+            .outputs_data(outputs_data.pack())
+            .cell_deps(self.registry.iter().map(|out_point| CellDep::new_builder().out_point(out_point.clone()).build()))
+            .build();
+        self.context.complete_tx(tx)
+    }
+}
+
+// A live order cell.
+pub struct OrderFixture {
+    pub user_lock: Script,
+    pub lock: Script,
+    pub input: CellInput,
+    pub capacity: u64,
+    // xudt in the order cell, sell orders only
+    pub xudt: u128,
+}
+
+// One pool, identified by token | type_id, and its live cells.
+pub struct PoolFixture {
+    pub deployment: Deployment,
+    pub issuer_lock: Script,
+    pub creator_lock: Script,
+    // lock of the unique cell, the aggregator spends it in every trade
+    pub aggregator_lock: Script,
+    pub admin_lock: Script,
+    pub token: Vec<u8>,
+    pub token_type: Script,
+    pub unique_type: Script,
+    pub metadata_type: Script,
+    pub curve_lock: Script,
+    // status | fee_bps | admin_lock_hash, empty for pools without admin
+    pub governance: Vec<u8>,
+    // xUDT pools created with proof that the issuance is closed
+    pub issuance_closed: bool,
+    pub fee_bps: u16,
+    // issuer cell spent first by the genesis transaction, the type_id is derived from it
+    pub genesis_input: CellInput,
+    // Type ID cell owning the xUDT, the genesis transaction burns it
+    pub owner_input: Option<CellInput>,
+    // live reserve cells and unique cell
+    pub cells: Vec<CellInput>,
+    pub xudt_reserve: u128,
+    pub ckb_reserve: u64,
+}
+
+impl PoolFixture {
+    pub fn new(standard: u8) -> Self {
+        Self::build(standard, standard == TOKEN_STANDARD_XUDT, None, false)
+    }
+
+    pub fn with_admin(standard: u8, status: u8, fee_bps: u16) -> Self {
+        Self::build(standard, standard == TOKEN_STANDARD_XUDT, Some((status, fee_bps)), false)
+    }
+
+    // An xUDT pool whose genesis proves the issuance closed.
+    pub fn with_closed_issuance() -> Self {
+        Self::build(TOKEN_STANDARD_XUDT, true, None, true)
+    }
+
+    // A pool trading an xUDT token declared as standard.
+    pub fn with_xudt_declared_as(standard: u8) -> Self {
+        Self::build(standard, true, None, false)
+    }
+
+    fn build(standard: u8, xudt: bool, admin: Option<(u8, u16)>, issuance_closed: bool) -> Self {
+        let mut deployment = Deployment::default();
+        let issuer_lock = deployment.lock("issuer");
+        let creator_lock = deployment.lock("creator");
+        let aggregator_lock = deployment.lock("aggregator");
+        let admin_lock = deployment.lock("admin");
+        let (token_type, owner_input) = if issuance_closed {
+            let owner_type = type_id_script(&ISSUANCE_OWNER_ARGS);
+            let owner_input = deployment.input(cell(&issuer_lock, Some(&owner_type), CELL_CAPACITY), Bytes::new());
+            (deployment.owned_xudt(&owner_type), Some(owner_input))
+        } else if xudt {
+            (deployment.xudt(&issuer_lock), None)
+        } else {
+            (deployment.custom_token(&issuer_lock), None)
+        };
+        let genesis_input = deployment.input(cell(&issuer_lock, None, CELL_CAPACITY * 100), Bytes::new());
+
+        let token = token_bytes(standard, &token_type);
+        let mut args = token.clone();
+        args.extend_from_slice(&type_id(&genesis_input, UNIQUE_CELL_INDEX));
+        let unique_type = deployment
+            .context
+            .build_script(&deployment.manager_out_point, Bytes::from(args.clone()))
+            .expect("script");
+        args.push(METADATA_FLAG);
+        let metadata_type = deployment
+            .context
+            .build_script(&deployment.manager_out_point, args.into())
+            .expect("script");
+        let mut args = token.clone();
+        args.extend_from_slice(unique_type.calc_script_hash().as_slice());
+        let curve_lock = deployment
+            .context
+            .build_script(&deployment.curve_out_point, args.into())
+            .expect("script");
+
+        let mut governance = Vec::new();
+        if let Some((status, fee_bps)) = admin {
+            governance.push(status);
+            governance.extend_from_slice(&fee_bps.to_le_bytes());
+            governance.extend_from_slice(admin_lock.calc_script_hash().as_slice());
+        }
+        PoolFixture {
+            deployment,
+            issuer_lock,
+            creator_lock,
+            aggregator_lock,
+            admin_lock,
+            token,
+            token_type,
+            unique_type,
+            metadata_type,
+            curve_lock,
+            governance,
+            issuance_closed,
+            fee_bps: admin.map(|(_, fee_bps)| fee_bps).unwrap_or(0),
+            genesis_input,
+            owner_input,
+            cells: Vec::new(),
+            xudt_reserve: 0,
+            ckb_reserve: 0,
+        }
+    }
+
+    pub fn user(&mut self, name: &str) -> Script {
+        self.deployment.lock(name)
+    }
+
+    pub fn pool_data(&self, xudt_liquidity: u128, ckb_liquidity: u64) -> Bytes {
+        let mut data = pool_data(xudt_liquidity, ckb_liquidity, &self.curve_lock, self.issuance_closed).to_vec();
+        data.extend_from_slice(&self.governance);
+        data.into()
+    }
+
+    pub fn unique_cell(&self, xudt_liquidity: u128, ckb_liquidity: u64) -> (CellOutput, Bytes) {
+        (
+            cell(&self.aggregator_lock, Some(&self.unique_type), CELL_CAPACITY),
+            self.pool_data(xudt_liquidity, ckb_liquidity),
+        )
+    }
+
+    // reserve cells and unique cell of the pool holding xudt and ckb
+    pub fn reserve_outputs(&self, xudt: u128, ckb: u64) -> Vec<(CellOutput, Bytes)> {
+        vec![
+            (cell(&self.curve_lock, Some(&self.token_type), CELL_CAPACITY), udt_data(xudt)),
+            (cell(&self.curve_lock, None, ckb), Bytes::new()),
+            self.unique_cell(xudt, ckb),
+        ]
+    }
+
+    // tx with the first three outputs replaced by the pool holding xudt and ckb
+    pub fn set_reserves(&self, tx: &TransactionView, xudt: u128, ckb: u64) -> TransactionView {
+        self.reserve_outputs(xudt, ckb)
+            .into_iter()
+            .enumerate()
+            .fold(tx.clone(), |tx, (i, (output, data))| replace_output(&tx, i, output, data))
+    }
+
+    // The pool after genesis and some trades, holding xudt and ckb.
+    pub fn open(&mut self, xudt: u128, ckb: u64) {
+        self.open_split(xudt, ckb, 1);
+    }
+
+    // Like open, with the reserves split over `cells` xudt cells and `cells`
+    // ckb cells. The next transaction merges them.
+    pub fn open_split(&mut self, xudt: u128, ckb: u64, cells: usize) {
+        let mut outputs = Vec::new();
+        for i in 0..cells {
+            let part_xudt = xudt / cells as u128 + if i == 0 { xudt % cells as u128 } else { 0 };
+            let part_ckb = ckb / cells as u64 + if i == 0 { ckb % cells as u64 } else { 0 };
+            outputs.push((cell(&self.curve_lock, Some(&self.token_type), CELL_CAPACITY), udt_data(part_xudt)));
+            outputs.push((cell(&self.curve_lock, None, part_ckb), Bytes::new()));
+        }
+        outputs.push(self.unique_cell(xudt, ckb));
+        self.cells = outputs
+            .into_iter()
+            .map(|(output, data)| self.deployment.input(output, data))
+            .collect();
+        self.xudt_reserve = xudt;
+        self.ckb_reserve = ckb;
+    }
+
+    // The issuer mints the whole supply. creator_buy goes to the creator, the
+    // rest to the reserve cells together with reserve_ckb. With the issuance
+    // closed, the owner cell is spent and not recreated.
+    pub fn create_pool(&mut self, creator_buy: u128, reserve_ckb: u64) -> TransactionView {
+        let mut outputs = self.reserve_outputs(TOTAL_XUDT_SUPPLY - creator_buy, reserve_ckb);
+        outputs.push((
+            cell(&self.creator_lock, Some(&self.metadata_type), CELL_CAPACITY),
+            metadata_data(&[]),
+        ));
+        if creator_buy > 0 {
+            outputs.push((
+                cell(&self.creator_lock, Some(&self.token_type), CELL_CAPACITY),
+                udt_data(creator_buy),
+            ));
+        }
+        let tx = self.deployment.build_tx(vec![self.genesis_input.clone()], outputs);
+        // Type ID is built into the chain, complete_tx finds no cell dep for it
+        tx.as_advanced_builder().inputs(self.owner_input.clone()).build()
+    }
+
+    // args: bondings_curve_lock_hash | user_lock_hash | token | slip_point | desired_amount | ckb_in
+    pub fn order_lock(&mut self, user_lock: &Script, slip_point: u16, desired_amount: u128, ckb_in: Option<u64>) -> Script {
+        let mut args = self.curve_lock.calc_script_hash().as_slice().to_vec();
+        args.extend_from_slice(user_lock.calc_script_hash().as_slice());
+        args.extend_from_slice(&self.token);
+        args.extend_from_slice(&slip_point.to_be_bytes());
+        args.extend_from_slice(&desired_amount.to_be_bytes());
+        if let Some(ckb_in) = ckb_in {
+            args.extend_from_slice(&ckb_in.to_be_bytes());
+        }
+        self.deployment
+            .context
+            .build_script(&self.deployment.order_out_point, args.into())
+            .expect("script")
+    }
+
+    fn place_order(&mut self, user_lock: &Script, lock: Script, capacity: u64, xudt: u128) -> OrderFixture {
+        let input = if xudt > 0 {
+            self.deployment
+                .input(cell(&lock, Some(&self.token_type), capacity), udt_data(xudt))
+        } else {
+            self.deployment.input(cell(&lock, None, capacity), Bytes::new())
+        };
+        OrderFixture {
+            user_lock: user_lock.clone(),
+            lock,
+            input,
+            capacity,
+            xudt,
+        }
+    }
+
+    pub fn buy_cost(&self, amount: u128) -> u64 {
+        with_buy_fee(quote_buy(self.xudt_reserve, amount).expect("quote"), self.fee_bps).expect("fee") as u64
+    }
+
+    pub fn sell_proceeds(&self, amount: u128) -> u64 {
+        with_sell_fee(quote_sell(self.xudt_reserve, amount).expect("quote"), self.fee_bps).expect("fee") as u64
+    }
+
+    // Buy order for amount at the current price, its capacity also covers the
+    // user's xudt cell.
+    pub fn buy_order(&mut self, user_lock: &Script, amount: u128, slip_point: u16) -> OrderFixture {
+        let lock = self.order_lock(user_lock, slip_point, amount, None);
+        let capacity = self.buy_cost(amount) + CELL_CAPACITY;
+        self.place_order(user_lock, lock, capacity, 0)
+    }
+
+    // Buy order spending at most ckb_in for what it buys at the current price.
+    pub fn buy_exact_in_order(&mut self, user_lock: &Script, ckb_in: u64, slip_point: u16) -> OrderFixture {
+        let amount = xudt_for_ckb(self.xudt_reserve, ckb_in as u128).expect("quote");
+        let lock = self.order_lock(user_lock, slip_point, amount, Some(ckb_in));
+        self.place_order(user_lock, lock, ckb_in + CELL_CAPACITY, 0)
+    }
+
+    // Sell orders receive ckb, the curve bounds how much.
+    pub fn sell_order(&mut self, user_lock: &Script, amount: u128) -> OrderFixture {
+        let lock = self.order_lock(user_lock, 0, 0, None);
+        self.place_order(user_lock, lock, CELL_CAPACITY, amount)
+    }
+
+    // The pool sells amount to the order at the curve price. The user gets the
+    // rest of the order capacity with the xudt cell.
+    pub fn fill_buy(&mut self, order: &OrderFixture, amount: u128) -> TransactionView {
+        self.fill_buys(&[(order, amount)])
+    }
+
+    // The pool fills several buy orders in one transaction, each one at the
+    // curve price after the orders before it.
+    pub fn fill_buys(&mut self, fills: &[(&OrderFixture, u128)]) -> TransactionView {
+        let mut inputs = self.cells.clone();
+        let mut user_outputs = Vec::new();
+        let mut sold = 0;
+        for (order, amount) in fills {
+            let cost = with_buy_fee(quote_buy(self.xudt_reserve - sold, *amount).expect("quote"), self.fee_bps)
+                .expect("fee") as u64;
+            inputs.push(order.input.clone());
+            user_outputs.push((
+                cell(&order.user_lock, Some(&self.token_type), order.capacity - cost),
+                udt_data(*amount),
+            ));
+            sold += amount;
+        }
+        let cost = self.buy_cost(sold);
+        let mut outputs = self.reserve_outputs(self.xudt_reserve - sold, self.ckb_reserve + cost);
+        outputs.extend(user_outputs);
+        self.deployment.build_tx(inputs, outputs)
+    }
+
+    // The pool buys the xudt of the order at the curve price.
+    pub fn fill_sell(&mut self, order: &OrderFixture) -> TransactionView {
+        self.fill_sells(&[order])
+    }
+
+    // The pool fills several sell orders in one transaction, each one at the
+    // curve price after the orders before it.
+    pub fn fill_sells(&mut self, orders: &[&OrderFixture]) -> TransactionView {
+        let mut inputs = self.cells.clone();
+        let mut user_outputs = Vec::new();
+        let mut bought = 0;
+        for order in orders {
+            let proceeds = with_sell_fee(quote_sell(self.xudt_reserve + bought, order.xudt).expect("quote"), self.fee_bps)
+                .expect("fee") as u64;
+            inputs.push(order.input.clone());
+            user_outputs.push((cell(&order.user_lock, None, order.capacity + proceeds), Bytes::new()));
+            bought += order.xudt;
+        }
+        let proceeds = self.sell_proceeds(bought);
+        let mut outputs = self.reserve_outputs(self.xudt_reserve + bought, self.ckb_reserve - proceeds);
+        outputs.extend(user_outputs);
+        self.deployment.build_tx(inputs, outputs)
+    }
+
+    pub fn buy(&mut self, user_lock: &Script, amount: u128, slip_point: u16) -> TransactionView {
+        let order = self.buy_order(user_lock, amount, slip_point);
+        self.fill_buy(&order, amount)
+    }
+
+    pub fn buy_exact_in(&mut self, user_lock: &Script, ckb_in: u64, slip_point: u16) -> TransactionView {
+        let order = self.buy_exact_in_order(user_lock, ckb_in, slip_point);
+        let amount = xudt_for_ckb(self.xudt_reserve, ckb_in as u128).expect("quote");
+        self.fill_buy(&order, amount)
+    }
+
+    pub fn sell(&mut self, user_lock: &Script, amount: u128) -> TransactionView {
+        let order = self.sell_order(user_lock, amount);
+        self.fill_sell(&order)
+    }
+
+    fn utxoswap_outputs(&self) -> Vec<(CellOutput, Bytes)> {
+        vec![
+            (
+                cell(&utxoswap_lock(), Some(&self.token_type), CELL_CAPACITY),
+                udt_data(self.xudt_reserve),
+            ),
+            (cell(&utxoswap_lock(), None, self.ckb_reserve), Bytes::new()),
+            self.unique_cell(0, 0),
+        ]
+    }
+
+    // The whole reserve goes to UTXOSwap.
+    pub fn graduate(&mut self) -> TransactionView {
+        let outputs = self.utxoswap_outputs();
+        self.deployment.build_tx(self.cells.clone(), outputs)
+    }
+
+    // Governance status of the unique cells built from now on, the pool needs an admin.
+    pub fn set_status(&mut self, status: u8) {
+        self.governance[0] = status;
+    }
+
+    // A cell of the admin, unlocking governance changes in the same transaction.
+    pub fn admin_input(&mut self) -> CellInput {
+        let admin_lock = self.admin_lock.clone();
+        self.deployment.input(cell(&admin_lock, None, CELL_CAPACITY), Bytes::new())
+    }
+
+    // Proposal of the admin for the pool governance, consumed GOVERNANCE_DELAY
+    // after it was created.
+    pub fn proposal_input(&mut self, fee_bps: u16, curve_lock: Option<&Script>) -> CellInput {
+        let mut args = self.unique_type.args().raw_data().to_vec();
+        args.push(PROPOSAL_FLAG);
+        let proposal_type = self
+            .deployment
+            .context
+            .build_script(&self.deployment.manager_out_point, args.into())
+            .expect("script");
+        let mut data = fee_bps.to_le_bytes().to_vec();
+        data.extend_from_slice(self.admin_lock.calc_script_hash().as_slice());
+        if let Some(curve_lock) = curve_lock {
+            data.extend_from_slice(&script_ref(curve_lock));
+        }
+        let admin_lock = self.admin_lock.clone();
+        // relative since, timestamp metric
+        let since = 0xc000_0000_0000_0000 | GOVERNANCE_DELAY;
+        self.deployment
+            .input(cell(&admin_lock, Some(&proposal_type), CELL_CAPACITY), data.into())
+            .as_builder()
+            .since(since.pack())
+            .build()
+    }
+
+    // The admin moves the reserves to the curve deployed as `curve`. The pool
+    // uses the new curve lock from now on.
+    pub fn migrate(&mut self, curve: &Script) -> TransactionView {
+        let curve_lock = curve.clone().as_builder().args(self.curve_lock.args()).build();
+        let mut inputs = self.cells.clone();
+        inputs.push(self.admin_input());
+        inputs.push(self.proposal_input(self.fee_bps, Some(&curve_lock)));
+        self.curve_lock = curve_lock;
+        let outputs = self.reserve_outputs(self.xudt_reserve, self.ckb_reserve);
+        self.deployment.build_tx(inputs, outputs)
+    }
+
+    // The admin withdraws the whole reserve to UTXOSwap and marks the pool graduated.
+    pub fn emergency_graduate(&mut self) -> TransactionView {
+        self.set_status(POOL_STATUS_GRADUATED);
+        let mut inputs = self.cells.clone();
+        inputs.push(self.admin_input());
+        let outputs = self.utxoswap_outputs();
+        self.deployment.build_tx(inputs, outputs)
+    }
+
+    // The user takes the order back, unlocking one of their cells in the same transaction.
+    pub fn cancel(&mut self, order: &OrderFixture) -> TransactionView {
+        self.cancel_orders(&[order])
+    }
+
+    // The user takes several of their orders back in one transaction.
+    pub fn cancel_orders(&mut self, orders: &[&OrderFixture]) -> TransactionView {
+        let user_lock = &orders[0].user_lock;
+        let user_input = self
+            .deployment
+            .input(cell(user_lock, None, CELL_CAPACITY), Bytes::new());
+        let mut inputs: Vec<_> = orders.iter().map(|order| order.input.clone()).collect();
+        inputs.push(user_input);
+        let xudt: u128 = orders.iter().map(|order| order.xudt).sum();
+        let capacity = orders.iter().map(|order| order.capacity).sum::<u64>() + CELL_CAPACITY;
+        let output = if xudt > 0 {
+            (cell(user_lock, Some(&self.token_type), capacity), udt_data(xudt))
+        } else {
+            (cell(user_lock, None, capacity), Bytes::new())
+        };
+        self.deployment.build_tx(inputs, vec![output])
+    }
+
+    // The creator sets arweave_tx_id, spending the metadata cell.
+    pub fn set_arweave_tx_id(&mut self, metadata: &CellInput, arweave_tx_id: &[u8]) -> TransactionView {
+        let output = cell(&self.creator_lock, Some(&self.metadata_type), CELL_CAPACITY);
+        self.deployment
+            .build_tx(vec![metadata.clone()], vec![(output, metadata_data(arweave_tx_id))])
+    }
+
+    pub fn verify(&self, tx: &TransactionView) -> Result<Cycle, Error> {
+        verify_tx(&self.deployment.context, tx, MAX_CYCLES)
+    }
+
+    // Make the outputs of a verified tx live and return them as inputs. When
+    // tx outputs the unique cell, it holds the new state of the pool.
+    pub fn commit(&mut self, tx: &TransactionView) -> Vec<CellInput> {
+        let mut inputs = Vec::new();
+        let mut pool_cells = Vec::new();
+        let (mut xudt, mut ckb) = (0u128, 0u64);
+        let mut unique_found = false;
+        for (i, (output, data)) in tx.outputs_with_data_iter().enumerate() {
+            let out_point = OutPoint::new(tx.hash(), i as u32);
+            self.deployment
+                .context
+                .create_cell_with_out_point(out_point.clone(), output.clone(), data.clone());
+            let input = CellInput::new_builder().previous_output(out_point).build();
+            if output.lock() == self.curve_lock {
+                match output.type_().to_opt() {
+                    Some(_) => xudt += u128::from_le_bytes(data[..16].try_into().unwrap()),
+                    None => ckb += Unpack::<u64>::unpack(&output.capacity()),
+                }
+                pool_cells.push(input.clone());
+            } else if output.type_().to_opt().as_ref() == Some(&self.unique_type) {
+                unique_found = true;
+                pool_cells.push(input.clone());
+            }
+            inputs.push(input);
+        }
+        if unique_found {
+            self.cells = pool_cells;
+            self.xudt_reserve = xudt;
+            self.ckb_reserve = ckb;
+        }
+        inputs
+    }
+}
@@ -0,0 +1,95 @@
+// Local chain stand-in for running the aggregator end to end.
+//
+// The live cells are the cells of a ckb-testtool Context. submit checks a
+// transaction like a node would, scripts as well as capacities, then spends
+// its inputs and makes its outputs live, so the next aggregator round sees the
+// new state through the Indexer implementation. Lock scripts are known once a
+// cell of the lock was live, like an indexer learning them from transactions.
+
+use ckb_testtool::{
+    ckb_error::Error,
+    ckb_types::{
+        core::{Capacity, Cycle, TransactionView},
+        packed::{Byte32, OutPoint, Script},
+        prelude::*,
+    },
+    context::Context,
+};
+use ckbfi_aggregator::{Indexer, LiveCell, SearchKey};
+use std::collections::HashMap;
+
+use crate::{builder::MAX_CYCLES, verify_tx};
+
+pub struct LocalChain<'a> {
+    pub context: &'a mut Context,
+    locks: HashMap<Byte32, Script>,
+}
+
+impl<'a> LocalChain<'a> {
+    pub fn new(context: &'a mut Context) -> Self {
+        let locks = context
+            .cells
+            .values()
+            .map(|(output, _)| (output.lock().calc_script_hash(), output.lock()))
+            .collect();
+        LocalChain { context, locks }
+    }
+
+    // Verify tx and commit it.
+    pub fn submit(&mut self, tx: &TransactionView) -> Result<Cycle, Error> {
+        let cycles = verify_tx(self.context, tx, MAX_CYCLES)?;
+        let mut input_capacity = 0u64;
+        for input in tx.inputs() {
+            let out_point = input.previous_output();
+            let (output, _) = self.context.cells.remove(&out_point).expect("live input");
+            input_capacity += Unpack::<u64>::unpack(&output.capacity());
+        }
+        let mut output_capacity = 0u64;
+        for (i, (output, data)) in tx.outputs_with_data_iter().enumerate() {
+            let capacity: u64 = output.capacity().unpack();
+            let occupied = output
+                .occupied_capacity(Capacity::bytes(data.len()).unwrap())
+                .unwrap()
+                .as_u64();
+            assert!(capacity >= occupied, "output {} holds {} of {} occupied", i, capacity, occupied);
+            output_capacity += capacity;
+            self.locks.insert(output.lock().calc_script_hash(), output.lock());
+            self.context
+                .create_cell_with_out_point(OutPoint::new(tx.hash(), i as u32), output, data);
+        }
+        assert!(
+            input_capacity >= output_capacity,
+            "outputs hold {} of {} input capacity",
+            output_capacity,
+            input_capacity
+        );
+        Ok(cycles)
+    }
+
+    pub fn live_cell(&self, out_point: &OutPoint) -> Option<LiveCell> {
+        self.context.cells.get(out_point).map(|(output, data)| LiveCell {
+            out_point: out_point.clone(),
+            output: output.clone(),
+            data: data.clone(),
+        })
+    }
+}
+
+impl Indexer for LocalChain<'_> {
+    fn get_cells(&self, key: &SearchKey) -> Result<Vec<LiveCell>, ckbfi_aggregator::Error> {
+        let mut cells: Vec<_> = self
+            .context
+            .cells
+            .keys()
+            .filter_map(|out_point| self.live_cell(out_point))
+            .filter(|cell| key.matches(&cell.output))
+            .collect();
+        // the context keeps no block order, any stable order does
+        cells.sort_by(|a, b| a.out_point.as_slice().cmp(b.out_point.as_slice()));
+        Ok(cells)
+    }
+
+    fn get_lock_script(&self, lock_hash: &Byte32) -> Result<Option<Script>, ckbfi_aggregator::Error> {
+        Ok(self.locks.get(lock_hash).cloned())
+    }
+}
@@ -0,0 +1,139 @@
+// Property tests of the ckbfi-curve math the bondings-curve contract checks
+// trades with, against an arbitrary precision model of the same curve.
+
+use ckbfi_curve::*;
+use num_bigint::BigUint;
+use proptest::prelude::*;
+
+// Mirrors of the private curve parameters. The k-th whole token, counted from
+// SUPPLY_OFFSET, costs 6 * k^2 / DG shannons.
+const SUPPLY_OFFSET: u128 = 100_000_000;
+const DG: u128 = 114_500_000_000_000;
+
+const LAUNCH_TOKENS: u128 = LAUNCH_XUDT_AMOUNT / XUDT_UNIT;
+const TOTAL_TOKENS: u128 = TOTAL_XUDT_SUPPLY / XUDT_UNIT;
+
+// Exact cost of the tokens [from, to) of the curve, times DG.
+fn reference_cost(from: u128, to: u128) -> BigUint {
+    // 6 * (0^2 + 1^2 + ... + (n - 1)^2)
+    let squares = |n: u128| {
+        let n = BigUint::from(n);
+        (&n - 1u8) * &n * (&n * 2u8 - 1u8)
+    };
+    squares(to + SUPPLY_OFFSET) - squares(from + SUPPLY_OFFSET)
+}
+
+// A pool holding between LAUNCH_XUDT_AMOUNT and TOTAL_XUDT_SUPPLY whole tokens
+// and a whole token amount the pool can sell from it.
+fn pool_and_buy() -> impl Strategy<Value = (u128, u128)> {
+    (LAUNCH_TOKENS..=TOTAL_TOKENS).prop_flat_map(|pool| {
+        (Just(pool * XUDT_UNIT), (0..=pool - LAUNCH_TOKENS).prop_map(|v| v * XUDT_UNIT))
+    })
+}
+
+proptest! {
+    #[test]
+    fn test_buy_price_matches_reference((pool, amount) in pool_and_buy()) {
+        let supply = supply(pool).unwrap() / XUDT_UNIT;
+        let price = get_buy_price(supply * XUDT_UNIT, amount).unwrap();
+        let exact = reference_cost(supply, supply + amount / XUDT_UNIT);
+        // the price is the difference of two rounded down sums, so it is
+        // less than a shannon away from the exact cost
+        let scaled = BigUint::from(price) * DG;
+        prop_assert!(scaled.clone() + DG > exact && scaled < exact + DG);
+    }
+
+    #[test]
+    fn test_sell_price_matches_buy_price((pool, amount) in pool_and_buy()) {
+        let sold = pool - amount;
+        prop_assert_eq!(
+            get_sell_price(supply(sold).unwrap(), amount).unwrap(),
+            get_buy_price(supply(pool).unwrap(), amount).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_buy_price_monotonic((pool, amount) in pool_and_buy(), more in 0..1_000_000u128) {
+        let price = quote_buy(pool, amount).unwrap();
+        let more = more * XUDT_UNIT;
+        if amount + more <= pool - LAUNCH_XUDT_AMOUNT {
+            // more tokens cost more
+            prop_assert!(quote_buy(pool, amount + more).unwrap() >= price);
+            // the same tokens cost more once the pool has sold some
+            prop_assert!(quote_buy(pool - more, amount).unwrap() >= price);
+        }
+    }
+
+    #[test]
+    fn test_buy_price_additive((pool, amount) in pool_and_buy(), split in 0..=100u128) {
+        let first = amount / XUDT_UNIT * split / 100 * XUDT_UNIT;
+        let second = amount - first;
+        prop_assert_eq!(
+            quote_buy(pool, first).unwrap() + quote_buy(pool - first, second).unwrap(),
+            quote_buy(pool, amount).unwrap()
+        );
+    }
+
+    // Any sequence of buys sold back in one go returns at most the ckb paid,
+    // with and without fees. Amounts the curve rejects are not traded.
+    #[test]
+    fn test_buy_then_sell_never_profits(
+        buys in prop::collection::vec(0..50_000_000 * XUDT_UNIT, 1..8),
+        fee_bps in 0..=1_000u16,
+    ) {
+        let mut pool = TOTAL_XUDT_SUPPLY;
+        let mut paid = 0u128;
+        for amount in buys {
+            if let Ok(cost) = quote_buy(pool, amount) {
+                paid += with_buy_fee(cost, fee_bps).unwrap();
+                pool -= amount;
+            }
+        }
+        let sold = TOTAL_XUDT_SUPPLY - pool;
+        let received = with_sell_fee(quote_sell(pool, sold).unwrap(), fee_bps).unwrap();
+        prop_assert!(received <= paid);
+    }
+
+    #[test]
+    fn test_fractional_amount_rejected((pool, amount) in pool_and_buy(), fraction in 1..XUDT_UNIT) {
+        prop_assert_eq!(quote_buy(pool, amount + fraction), Err(CurveError::FractionalAmount));
+        prop_assert_eq!(quote_sell(pool - amount - fraction, amount), Err(CurveError::FractionalAmount));
+    }
+
+    // Every function returns an error instead of panicking outside the curve
+    // range.
+    #[test]
+    fn test_no_panic(pool in any::<u128>(), amount in any::<u128>(), fee_bps in any::<u16>()) {
+        let _ = get_price(pool, amount);
+        let _ = get_buy_price(pool, amount);
+        let _ = get_sell_price(pool, amount);
+        let _ = quote_buy(pool, amount);
+        let _ = quote_sell(pool, amount);
+        let _ = xudt_for_ckb(pool, amount);
+        let _ = xudt_for_ckb_out(pool, amount);
+        let _ = with_buy_fee(amount, fee_bps);
+        let _ = without_buy_fee(amount, fee_bps);
+        let _ = with_sell_fee(amount, fee_bps);
+        let _ = creator_buy_cost(amount, fee_bps);
+        let _ = spot_price(pool);
+        let _ = price_impact_bps(pool, amount);
+    }
+
+    #[test]
+    fn test_no_panic_in_supply_range((pool, amount) in pool_and_buy(), ckb in any::<u64>()) {
+        prop_assert!(quote_buy(pool, amount).is_ok());
+        prop_assert!(quote_sell(pool, TOTAL_XUDT_SUPPLY - pool).is_ok());
+        prop_assert!(xudt_for_ckb(pool, ckb as u128).is_ok());
+        prop_assert!(price_impact_bps(pool, amount).is_ok());
+    }
+}
+
+// The whole curve, from an untouched pool down to the launch reserve.
+#[test]
+fn test_full_curve_matches_reference() {
+    let price = quote_buy(TOTAL_XUDT_SUPPLY, TOTAL_XUDT_SUPPLY - LAUNCH_XUDT_AMOUNT).unwrap();
+    let from = LAUNCH_TOKENS;
+    let exact = reference_cost(from, from + TOTAL_TOKENS - LAUNCH_TOKENS);
+    let scaled = BigUint::from(price) * DG;
+    assert!(scaled.clone() + DG > exact && scaled < exact + DG);
+}
@@ -0,0 +1,139 @@
+// Cycle budget of the transactions the aggregator sends.
+//
+// Measures the cycles of create, buy, sell, cancel and graduation with 1 to
+// MAX_BATCH pool cells and orders, and compares them with the baseline in
+// tests/cycles.json. Only the script groups of the ckbfi contracts count, xUDT
+// and the always success locks of the fixture aren't built by this repo. The aggregator pays fees proportional to cycles, so a
+// case exceeding its baseline by more than BUDGET_TOLERANCE_BPS fails, as
+// does a missing baseline or a case missing from it.
+//
+// Update the baseline after an intended change with `make cycles`.
+
+use crate::builder::*;
+use crate::replay::{contract_names, replay};
+use ckb_testtool::ckb_types::core::{Cycle, TransactionView};
+use ckbfi_curve::{LAUNCH_CKB_AMOUNT, LAUNCH_XUDT_AMOUNT, TOTAL_XUDT_SUPPLY, XUDT_UNIT};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+const MAX_BATCH: usize = 3;
+const BUDGET_TOLERANCE_BPS: u64 = 100;
+const UPDATE_ENV_VAR: &str = "UPDATE_CYCLE_BASELINE";
+
+const GENESIS_CKB: u64 = 10_000 * 100_000_000;
+const TRADE_AMOUNT: u128 = 1_000_000 * XUDT_UNIT;
+
+fn baseline_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("cycles.json")
+}
+
+fn measure(pool: &PoolFixture, tx: &TransactionView) -> Cycle {
+    pool.verify(tx).expect("pass verification");
+    let mock_tx = pool.deployment.context.dump_tx(tx).expect("dump tx").into();
+    let names = contract_names(&mock_tx);
+    replay(mock_tx, &names)
+        .expect("replay")
+        .into_iter()
+        .filter(|script| script.contract.is_some())
+        .map(|script| script.result.expect("pass verification"))
+        .sum()
+}
+
+fn create() -> Cycle {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    measure(&pool, &tx)
+}
+
+fn buy(cells: usize, orders: usize) -> Cycle {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    pool.open_split(TOTAL_XUDT_SUPPLY, GENESIS_CKB, cells);
+    let placed: Vec<_> = (0..orders)
+        .map(|i| {
+            let user = pool.user(&format!("user{}", i));
+            pool.buy_order(&user, TRADE_AMOUNT, 100)
+        })
+        .collect();
+    let fills: Vec<_> = placed.iter().map(|order| (order, TRADE_AMOUNT)).collect();
+    let tx = pool.fill_buys(&fills);
+    measure(&pool, &tx)
+}
+
+fn sell(cells: usize, orders: usize) -> Cycle {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    let sold = TRADE_AMOUNT * orders as u128;
+    pool.open_split(TOTAL_XUDT_SUPPLY - sold, GENESIS_CKB + 1_000_000 * 100_000_000, cells);
+    let placed: Vec<_> = (0..orders)
+        .map(|i| {
+            let user = pool.user(&format!("user{}", i));
+            pool.sell_order(&user, TRADE_AMOUNT)
+        })
+        .collect();
+    let orders: Vec<_> = placed.iter().collect();
+    let tx = pool.fill_sells(&orders);
+    measure(&pool, &tx)
+}
+
+fn cancel(orders: usize) -> Cycle {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let user = pool.user("user");
+    let placed: Vec<_> = (0..orders)
+        .map(|_| pool.buy_order(&user, TRADE_AMOUNT, 100))
+        .collect();
+    let orders: Vec<_> = placed.iter().collect();
+    let tx = pool.cancel_orders(&orders);
+    measure(&pool, &tx)
+}
+
+fn graduate(cells: usize) -> Cycle {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    pool.open_split(LAUNCH_XUDT_AMOUNT, LAUNCH_CKB_AMOUNT, cells);
+    let tx = pool.graduate();
+    measure(&pool, &tx)
+}
+
+fn measure_all() -> BTreeMap<String, Cycle> {
+    let mut cycles = BTreeMap::new();
+    cycles.insert("create".to_string(), create());
+    for cells in 1..=MAX_BATCH {
+        for orders in 1..=MAX_BATCH {
+            cycles.insert(format!("buy_{}_cells_{}_orders", cells, orders), buy(cells, orders));
+            cycles.insert(format!("sell_{}_cells_{}_orders", cells, orders), sell(cells, orders));
+        }
+        cycles.insert(format!("graduate_{}_cells", cells), graduate(cells));
+    }
+    for orders in 1..=MAX_BATCH {
+        cycles.insert(format!("cancel_{}_orders", orders), cancel(orders));
+    }
+    cycles
+}
+
+#[test]
+fn test_cycle_budget() {
+    let cycles = measure_all();
+    let path = baseline_path();
+    if std::env::var(UPDATE_ENV_VAR).is_ok() {
+        let json = serde_json::to_string_pretty(&cycles).expect("json");
+        std::fs::write(&path, json + "\n").expect("write cycle baseline");
+        return;
+    }
+    let data = std::fs::read(&path)
+        .unwrap_or_else(|err| panic!("read cycle baseline {:?}: {}, create it with `make cycles`", path, err));
+    let baseline: BTreeMap<String, Cycle> = serde_json::from_slice(&data).expect("cycle baseline");
+    let mut over_budget = Vec::new();
+    let mut missing = Vec::new();
+    for (case, &used) in &cycles {
+        match baseline.get(case) {
+            Some(&base) => {
+                println!("{}: {} cycles, baseline {}", case, used, base);
+                if used > base + base * BUDGET_TOLERANCE_BPS / 10_000 {
+                    over_budget.push(format!("{}: {} > {}", case, used, base));
+                }
+            }
+            None => missing.push(format!("{}: {}", case, used)),
+        }
+    }
+    assert!(missing.is_empty(), "cases without baseline, update it with `make cycles`: {:?}", missing);
+    assert!(over_budget.is_empty(), "cycles over budget: {:?}", over_budget);
+}
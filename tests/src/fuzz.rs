@@ -0,0 +1,299 @@
+// Adversarial transaction fuzzer.
+//
+// Starts from a valid create, buy or sell transaction built by PoolFixture,
+// applies a random sequence of mutations an attacker controls (adding and
+// removing pool cells, changing args lengths, swapping type scripts,
+// duplicating user outputs, moving capacity and xudt around) and verifies the
+// result against the compiled contracts. A mutated transaction is allowed to
+// verify only if the pool ends up paid at least the curve price for what left
+// it and no xudt was minted.
+
+use crate::builder::*;
+use ckb_testtool::ckb_types::{bytes::Bytes, core::TransactionView, packed::*, prelude::*};
+use ckbfi_curve::{quote_buy, quote_sell, with_buy_fee, with_sell_fee, TOTAL_XUDT_SUPPLY, XUDT_UNIT};
+use proptest::prelude::*;
+
+const GENESIS_CKB: u64 = 10_000 * 100_000_000;
+const BUY_AMOUNT: u128 = 10_000_000 * XUDT_UNIT;
+
+#[derive(Debug, Clone, Copy)]
+enum Base {
+    Create,
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TypeSwap {
+    Remove,
+    // an xUDT issued by the attacker
+    FakeToken,
+    Token,
+    Unique,
+}
+
+// Indices are taken modulo the number of inputs or outputs.
+#[derive(Debug, Clone)]
+enum Mutation {
+    RemoveInput(usize),
+    RemoveOutput(usize),
+    DuplicateOutput(usize),
+    // an extra live cell locked by the pool, holding xudt or only ckb
+    AddPoolInput { xudt: bool },
+    AddPoolOutput { xudt: bool },
+    OutputCapacity(usize, i64),
+    OutputXudt(usize, i128),
+    OutputType(usize, TypeSwap),
+    InputType(usize, TypeSwap),
+    OutputLockArgs(usize, usize),
+    InputLockArgs(usize, usize),
+    // the output goes to the attacker instead
+    OutputLock(usize),
+}
+
+fn type_swap() -> impl Strategy<Value = TypeSwap> {
+    prop_oneof![
+        Just(TypeSwap::Remove),
+        Just(TypeSwap::FakeToken),
+        Just(TypeSwap::Token),
+        Just(TypeSwap::Unique),
+    ]
+}
+
+fn mutation() -> impl Strategy<Value = Mutation> {
+    let index = 0..8usize;
+    let capacity = -(CELL_CAPACITY as i64)..=CELL_CAPACITY as i64;
+    let xudt = -(BUY_AMOUNT as i128)..=BUY_AMOUNT as i128;
+    prop_oneof![
+        index.clone().prop_map(Mutation::RemoveInput),
+        index.clone().prop_map(Mutation::RemoveOutput),
+        index.clone().prop_map(Mutation::DuplicateOutput),
+        any::<bool>().prop_map(|xudt| Mutation::AddPoolInput { xudt }),
+        any::<bool>().prop_map(|xudt| Mutation::AddPoolOutput { xudt }),
+        (index.clone(), capacity).prop_map(|(i, v)| Mutation::OutputCapacity(i, v)),
+        (index.clone(), xudt).prop_map(|(i, v)| Mutation::OutputXudt(i, v)),
+        (index.clone(), type_swap()).prop_map(|(i, v)| Mutation::OutputType(i, v)),
+        (index.clone(), type_swap()).prop_map(|(i, v)| Mutation::InputType(i, v)),
+        (index.clone(), 0..140usize).prop_map(|(i, v)| Mutation::OutputLockArgs(i, v)),
+        (index.clone(), 0..140usize).prop_map(|(i, v)| Mutation::InputLockArgs(i, v)),
+        index.prop_map(Mutation::OutputLock),
+    ]
+}
+
+// Pool, user and the valid transaction the mutations start from.
+fn base_tx(base: Base, fee_bps: u16) -> (PoolFixture, TransactionView) {
+    let standard = match base {
+        Base::Create => TOKEN_STANDARD_CUSTOM,
+        _ => TOKEN_STANDARD_XUDT,
+    };
+    let mut pool = PoolFixture::with_admin(standard, POOL_STATUS_ACTIVE, fee_bps);
+    let user = pool.user("user");
+    let tx = match base {
+        Base::Create => pool.create_pool(0, GENESIS_CKB),
+        Base::Buy => {
+            pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+            pool.buy(&user, BUY_AMOUNT, 100)
+        }
+        Base::Sell => {
+            pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+            let tx = pool.buy(&user, BUY_AMOUNT, 100);
+            pool.commit(&tx);
+            pool.sell(&user, BUY_AMOUNT / 2)
+        }
+    };
+    (pool, tx)
+}
+
+fn swap_type(pool: &mut PoolFixture, output: CellOutput, swap: TypeSwap) -> CellOutput {
+    let type_ = match swap {
+        TypeSwap::Remove => None,
+        TypeSwap::FakeToken => {
+            let attacker = pool.user("attacker");
+            Some(pool.deployment.xudt(&attacker))
+        }
+        TypeSwap::Token => Some(pool.token_type.clone()),
+        TypeSwap::Unique => Some(pool.unique_type.clone()),
+    };
+    output.as_builder().type_(type_.pack()).build()
+}
+
+// args cut or zero padded to len
+fn resize_lock_args(output: CellOutput, len: usize) -> CellOutput {
+    let mut args = output.lock().args().raw_data().to_vec();
+    args.resize(len, 0);
+    let lock = output.lock().as_builder().args(args.pack()).build();
+    output.as_builder().lock(lock).build()
+}
+
+fn input_cell(pool: &PoolFixture, input: &CellInput) -> (CellOutput, Bytes) {
+    pool.deployment
+        .context
+        .get_cell(&input.previous_output())
+        .expect("live cell")
+}
+
+// Mutated inputs are spent from new live cells.
+fn apply(pool: &mut PoolFixture, tx: &TransactionView, mutation: &Mutation) -> TransactionView {
+    let mut inputs: Vec<_> = tx.inputs().into_iter().collect();
+    let mut outputs: Vec<_> = tx.outputs_with_data_iter().collect();
+    let (n_in, n_out) = (inputs.len().max(1), outputs.len().max(1));
+    match *mutation {
+        Mutation::RemoveInput(i) if inputs.len() > 1 => {
+            inputs.remove(i % n_in);
+        }
+        Mutation::RemoveOutput(i) if !outputs.is_empty() => {
+            outputs.remove(i % n_out);
+        }
+        Mutation::DuplicateOutput(i) if !outputs.is_empty() => {
+            outputs.push(outputs[i % n_out].clone());
+        }
+        Mutation::AddPoolInput { xudt } => {
+            let (output, data) = pool_cell(pool, xudt);
+            inputs.push(pool.deployment.input(output, data));
+        }
+        Mutation::AddPoolOutput { xudt } => outputs.push(pool_cell(pool, xudt)),
+        Mutation::OutputCapacity(i, delta) if !outputs.is_empty() => {
+            let (output, _) = &mut outputs[i % n_out];
+            let capacity: u64 = output.capacity().unpack();
+            *output = output
+                .clone()
+                .as_builder()
+                .capacity(capacity.saturating_add_signed(delta).pack())
+                .build();
+        }
+        Mutation::OutputXudt(i, delta) if !outputs.is_empty() => {
+            let (_, data) = &mut outputs[i % n_out];
+            if data.len() >= 16 {
+                let amount = u128::from_le_bytes(data[..16].try_into().unwrap());
+                let amount = amount.saturating_add_signed(delta);
+                let mut bytes = data.to_vec();
+                bytes[..16].copy_from_slice(&amount.to_le_bytes());
+                *data = bytes.into();
+            }
+        }
+        Mutation::OutputType(i, swap) if !outputs.is_empty() => {
+            let (output, _) = &mut outputs[i % n_out];
+            *output = swap_type(pool, output.clone(), swap);
+        }
+        Mutation::InputType(i, swap) => {
+            let (output, data) = input_cell(pool, &inputs[i % n_in]);
+            let output = swap_type(pool, output, swap);
+            inputs[i % n_in] = pool.deployment.input(output, data);
+        }
+        Mutation::OutputLockArgs(i, len) if !outputs.is_empty() => {
+            let (output, _) = &mut outputs[i % n_out];
+            *output = resize_lock_args(output.clone(), len);
+        }
+        Mutation::InputLockArgs(i, len) => {
+            let (output, data) = input_cell(pool, &inputs[i % n_in]);
+            inputs[i % n_in] = pool.deployment.input(resize_lock_args(output, len), data);
+        }
+        Mutation::OutputLock(i) if !outputs.is_empty() => {
+            let attacker = pool.user("attacker");
+            let (output, _) = &mut outputs[i % n_out];
+            *output = output.clone().as_builder().lock(attacker).build();
+        }
+        _ => {}
+    }
+    pool.deployment.build_tx(inputs, outputs)
+}
+
+fn pool_cell(pool: &PoolFixture, xudt: bool) -> (CellOutput, Bytes) {
+    if xudt {
+        (cell(&pool.curve_lock, Some(&pool.token_type), CELL_CAPACITY), udt_data(BUY_AMOUNT))
+    } else {
+        (cell(&pool.curve_lock, None, CELL_CAPACITY), Bytes::new())
+    }
+}
+
+// xudt of the pool token and plain capacity held by cells locked by the pool,
+// the reserves as the pool data counts them. The capacity of the xudt cells
+// isn't reserve, merging them releases it.
+fn pool_value(pool: &PoolFixture, cells: impl Iterator<Item = (CellOutput, Bytes)>) -> (u128, u128) {
+    let (mut xudt, mut ckb) = (0u128, 0u128);
+    for (output, data) in cells {
+        if output.lock() != pool.curve_lock {
+            continue;
+        }
+        match output.type_().to_opt() {
+            None => ckb += Unpack::<u64>::unpack(&output.capacity()) as u128,
+            Some(type_) if type_ == pool.token_type && data.len() >= 16 => {
+                xudt += u128::from_le_bytes(data[..16].try_into().unwrap());
+            }
+            Some(_) => {}
+        }
+    }
+    (xudt, ckb)
+}
+
+fn token_amount(pool: &PoolFixture, cells: impl Iterator<Item = (CellOutput, Bytes)>) -> u128 {
+    cells
+        .filter(|(output, data)| output.type_().to_opt().as_ref() == Some(&pool.token_type) && data.len() >= 16)
+        .map(|(_, data)| u128::from_le_bytes(data[..16].try_into().unwrap()))
+        .sum()
+}
+
+// Why tx takes value out of the pool or mints xudt, None if it doesn't.
+fn value_leak(pool: &PoolFixture, tx: &TransactionView) -> Option<String> {
+    let input_cells: Vec<_> = tx
+        .inputs()
+        .into_iter()
+        .map(|input| input_cell(pool, &input))
+        .collect();
+    let genesis = tx.inputs().into_iter().any(|input| input == pool.genesis_input);
+    // the genesis transaction starts from a pool holding the whole supply
+    let ((x_in, c_in), minted) = if genesis {
+        ((TOTAL_XUDT_SUPPLY, 0), TOTAL_XUDT_SUPPLY)
+    } else {
+        (pool_value(pool, input_cells.iter().cloned()), token_amount(pool, input_cells.iter().cloned()))
+    };
+    let (x_out, c_out) = pool_value(pool, tx.outputs_with_data_iter());
+    let token_out = token_amount(pool, tx.outputs_with_data_iter());
+    if token_out > minted {
+        return Some(format!("xudt minted: {} > {}", token_out, minted));
+    }
+    let fee_bps = pool.fee_bps;
+    if x_out < x_in {
+        let paid = c_out.saturating_sub(c_in);
+        match quote_buy(x_in, x_in - x_out).and_then(|cost| with_buy_fee(cost, fee_bps)) {
+            Ok(cost) if paid >= cost => None,
+            cost => Some(format!("bought {} xudt paying {}, curve cost {:?}", x_in - x_out, paid, cost)),
+        }
+    } else if x_out > x_in {
+        let taken = c_in.saturating_sub(c_out);
+        match quote_sell(x_in, x_out - x_in).and_then(|proceeds| with_sell_fee(proceeds, fee_bps)) {
+            Ok(proceeds) if taken <= proceeds => None,
+            proceeds => Some(format!("sold {} xudt taking {}, curve proceeds {:?}", x_out - x_in, taken, proceeds)),
+        }
+    } else if c_out < c_in {
+        Some(format!("{} ckb taken without trading", c_in - c_out))
+    } else {
+        None
+    }
+}
+
+fn base() -> impl Strategy<Value = Base> {
+    prop_oneof![Just(Base::Create), Just(Base::Buy), Just(Base::Sell)]
+}
+
+proptest! {
+    // every case deploys the contracts in a new context
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_mutated_tx_never_leaks_value(
+        base in base(),
+        fee_bps in prop_oneof![Just(0u16), Just(100u16)],
+        mutations in prop::collection::vec(mutation(), 1..4),
+    ) {
+        let (mut pool, mut tx) = base_tx(base, fee_bps);
+        prop_assert!(pool.verify(&tx).is_ok(), "base transaction must verify");
+        for mutation in &mutations {
+            tx = apply(&mut pool, &tx, mutation);
+        }
+        if pool.verify(&tx).is_ok() {
+            let leak = value_leak(&pool, &tx);
+            prop_assert!(leak.is_none(), "{}", leak.unwrap_or_default());
+        }
+    }
+}
@@ -3,6 +3,7 @@ use ckb_testtool::{
     ckb_types::{
         bytes::Bytes,
         core::{Cycle, TransactionView},
+        packed::OutPoint,
     },
     context::Context,
 };
@@ -11,6 +12,20 @@ use std::fs;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+#[cfg(test)]
+mod aggregator;
+pub mod builder;
+pub mod chain;
+pub mod replay;
+#[cfg(test)]
+mod curve;
+// native simulator builds don't count cycles
+#[cfg(all(test, not(feature = "native-simulator")))]
+mod cycles;
+#[cfg(test)]
+mod fuzz;
+#[cfg(feature = "native-simulator")]
+mod simulator;
 #[cfg(test)]
 mod tests;
 
@@ -79,15 +94,40 @@ impl Loader {
         Loader(base_path)
     }
 
+    // Scripts deployed by others, e.g. xUDT, are bundled in tests/bins
+    pub fn bundled() -> Self {
+        let mut base_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        base_path.push("bins");
+        Loader(base_path)
+    }
+
     pub fn load_binary(&self, name: &str) -> Bytes {
-        let mut path = self.0.clone();
-        path.push(name);
-        let result = fs::read(&path);
-        if result.is_err() {
-            panic!("Binary {:?} is missing!", path);
+        match self.try_load_binary(name) {
+            Some(binary) => binary,
+            None => panic!("Binary {:?} is missing!", self.0.join(name)),
         }
-        result.unwrap().into()
     }
+
+    pub fn try_load_binary(&self, name: &str) -> Option<Bytes> {
+        fs::read(self.0.join(name)).ok().map(Into::into)
+    }
+}
+
+// Deploys one of the contracts built by this repo. With the native-simulator
+// feature, its native build runs in place of the binary.
+pub fn deploy_contract(context: &mut Context, name: &str) -> OutPoint {
+    let binary = Loader::default().load_binary(name);
+    #[cfg(feature = "native-simulator")]
+    simulator::register(name, &binary);
+    context.deploy_cell(binary)
+}
+
+// Context::verify_tx, also failing transactions rejected by a native build.
+pub fn verify_tx(context: &Context, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error> {
+    #[cfg(feature = "native-simulator")]
+    return simulator::verify_tx(context, tx, max_cycles);
+    #[cfg(not(feature = "native-simulator"))]
+    context.verify_tx(tx, max_cycles)
 }
 
 // This helper method runs Context::verify_tx, but in case error happens,
@@ -97,13 +137,17 @@ pub fn verify_and_dump_failed_tx(
     tx: &TransactionView,
     max_cycles: u64,
 ) -> Result<Cycle, Error> {
-    let result = context.verify_tx(tx, max_cycles);
+    let result = verify_tx(context, tx, max_cycles);
     if result.is_err() {
         let mut path = env::current_dir().expect("current dir");
         path.push("failed_txs");
         std::fs::create_dir_all(&path).expect("create failed_txs dir");
         let mock_tx = context.dump_tx(tx).expect("dump failed tx");
         let json = serde_json::to_string_pretty(&mock_tx).expect("json");
+        // contracts among the cell deps, for the replay binary
+        let names = replay::contract_names(&mock_tx.into());
+        let names_json = serde_json::to_string_pretty(&names).expect("json");
+        std::fs::write(path.join(format!("0x{:x}.contracts.json", tx.hash())), names_json).expect("write");
         path.push(format!("0x{:x}.json", tx.hash()));
         println!("Failed tx written to {:?}", path);
         std::fs::write(path, json).expect("write");
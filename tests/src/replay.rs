@@ -0,0 +1,128 @@
+// Re-verification of dumped mock transactions with the current build, used
+// by the replay binary.
+//
+// verify_and_dump_failed_tx writes failed_txs/0x<hash>.json and, next to it,
+// 0x<hash>.contracts.json naming the contracts among the cell deps by code
+// hash. Replaying puts the current build of those contracts in place of the
+// dumped binaries, so a fix can be checked against the exact transaction that
+// failed. Transactions exported from a chain name their contracts on the
+// command line instead.
+
+use crate::builder::MAX_CYCLES;
+use crate::Loader;
+use ckb_mock_tx_types::{MockTransaction, Resource};
+use ckb_testtool::{
+    ckb_chain_spec::consensus::ConsensusBuilder,
+    ckb_script::{ScriptError, ScriptGroup, TransactionScriptsVerifier, TxVerifyEnv},
+    ckb_types::{
+        core::{
+            cell::resolve_transaction,
+            hardfork::{HardForks, CKB2021, CKB2023},
+            Cycle, HeaderBuilder, ScriptHashType,
+        },
+        packed::{Byte32, CellOutput},
+        prelude::*,
+    },
+};
+use ckbfi_common::error::CONTRACTS;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+// "0x<code hash>" -> contract name, for data and type hashes
+pub type ContractNames = BTreeMap<String, String>;
+
+pub struct ScriptResult {
+    pub group: ScriptGroup,
+    pub contract: Option<String>,
+    pub result: Result<Cycle, ScriptError>,
+}
+
+pub fn hex(hash: &Byte32) -> String {
+    format!("0x{:x}", hash)
+}
+
+// Contracts of the current build among the cell deps of mock_tx.
+pub fn contract_names(mock_tx: &MockTransaction) -> ContractNames {
+    let loader = Loader::default();
+    let mut names = ContractNames::new();
+    for name in CONTRACTS.iter().map(|codes| codes.contract) {
+        let Some(binary) = loader.try_load_binary(name) else {
+            continue;
+        };
+        let data_hash = CellOutput::calc_data_hash(&binary);
+        for dep in &mock_tx.mock_info.cell_deps {
+            if CellOutput::calc_data_hash(&dep.data) != data_hash {
+                continue;
+            }
+            names.insert(hex(&data_hash), name.to_string());
+            if let Some(type_script) = dep.output.type_().to_opt() {
+                names.insert(hex(&type_script.calc_script_hash()), name.to_string());
+            }
+        }
+    }
+    names
+}
+
+fn script_contract(group: &ScriptGroup, names: &ContractNames) -> Option<String> {
+    names.get(&hex(&group.script.code_hash())).cloned()
+}
+
+// Verifies every script group of mock_tx. Cell deps referenced by type hash
+// of a named contract run the current build, deps referenced by data hash
+// can't be swapped and keep the dumped binary.
+pub fn replay(mut mock_tx: MockTransaction, names: &ContractNames) -> Result<Vec<ScriptResult>, String> {
+    let loader = Loader::default();
+    for dep in mock_tx.mock_info.cell_deps.iter_mut() {
+        let type_hash = dep.output.type_().to_opt().map(|script| hex(&script.calc_script_hash()));
+        if let Some(name) = type_hash.and_then(|hash| names.get(&hash)) {
+            dep.data = loader.load_binary(name);
+        }
+    }
+    let resource = Resource::from_mock_tx(&mock_tx)?;
+    let rtx = resolve_transaction(mock_tx.core_transaction(), &mut HashSet::new(), &resource, &resource)
+        .map_err(|err| err.to_string())?;
+    let consensus = ConsensusBuilder::default()
+        .hardfork_switch(HardForks {
+            ckb2021: CKB2021::new_dev_default(),
+            ckb2023: CKB2023::new_dev_default(),
+        })
+        .build();
+    let tip = HeaderBuilder::default().number(0.pack()).build();
+    let mut verifier = TransactionScriptsVerifier::new(
+        Arc::new(rtx),
+        resource,
+        Arc::new(consensus),
+        Arc::new(TxVerifyEnv::new_submit(&tip)),
+    );
+    verifier.set_debug_printer(|_id, msg| {
+        println!("[contract debug] {}", msg);
+    });
+    let groups: Vec<_> = verifier
+        .groups()
+        .map(|(hash, group)| (hash.clone(), group.clone()))
+        .collect();
+    Ok(groups
+        .into_iter()
+        .map(|(hash, group)| ScriptResult {
+            contract: script_contract(&group, names),
+            result: verifier.verify_single(group.group_type, &hash, MAX_CYCLES),
+            group,
+        })
+        .collect())
+}
+
+// "Lock of inputs [0, 1]" style location of a script group
+pub fn describe(group: &ScriptGroup) -> String {
+    let hash_type = match group.script.hash_type().try_into() {
+        Ok(ScriptHashType::Type) => "type",
+        _ => "data",
+    };
+    format!(
+        "{} script {} ({}), inputs {:?}, outputs {:?}",
+        group.group_type,
+        hex(&group.script.code_hash()),
+        hash_type,
+        group.input_indices,
+        group.output_indices
+    )
+}
@@ -0,0 +1,154 @@
+// Native simulator mode, enabled by the native-simulator feature.
+//
+// ckb-testtool runs a native build instead of a RISC-V binary once the build is
+// registered for the binary's code hash, so breakpoints and coverage work in
+// entry::main. It drops the exit code of native scripts though: the *-sim
+// libraries in native-simulators/ report the first non-zero one in
+// CKBFI_SIM_EXIT_CODE and verify_tx turns it back into a script error.
+//
+// Not every script group can run natively. ckb-testtool (0.14) starts a native
+// script from the first input of its group, so a type group with only outputs
+// (the unique cell and metadata cell mints) panics, and ckb-x64-simulator
+// (0.10) reads the data of GroupOutput index i from output i instead of the
+// group's i-th output. verify_tx registers the native builds for each
+// transaction and leaves out a contract with such a group, its groups in that
+// transaction run the RISC-V binary.
+
+use ckb_testtool::{
+    ckb_error::Error,
+    ckb_script::ScriptError,
+    ckb_types::{
+        bytes::Bytes,
+        core::{Cycle, ScriptHashType, TransactionView},
+        packed::{Byte32, CellOutput, OutPoint, Script},
+        prelude::*,
+    },
+    context::Context,
+};
+use ckbfi_common::script_ref::TYPE_ID_REF;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const EXIT_CODE_VAR: &str = "CKBFI_SIM_EXIT_CODE";
+
+// native build of each contract binary deployed, by code hash
+static SIMULATORS: Mutex<Vec<(Byte32, PathBuf)>> = Mutex::new(Vec::new());
+
+// Built by `make build` into the debug target dir, or the llvm-cov one when
+// CARGO_TARGET_DIR is set by `make coverage`.
+fn library_path(name: &str) -> PathBuf {
+    let mut path = match env::var("CARGO_TARGET_DIR") {
+        Ok(val) => PathBuf::from(val),
+        Err(_) => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..").join("target"),
+    };
+    path.push("debug");
+    path.push(format!(
+        "{}{}_sim{}",
+        env::consts::DLL_PREFIX,
+        name.replace('-', "_"),
+        env::consts::DLL_SUFFIX
+    ));
+    path
+}
+
+// Run the native build of contract name for scripts using binary.
+pub fn register(name: &str, binary: &Bytes) {
+    let path = library_path(name);
+    if !path.is_file() {
+        panic!("Native simulator {:?} is missing!", path);
+    }
+    let code_hash = CellOutput::calc_data_hash(binary);
+    let mut simulators = SIMULATORS.lock().unwrap_or_else(|err| err.into_inner());
+    if !simulators.iter().any(|(hash, _)| hash == &code_hash) {
+        simulators.push((code_hash, path));
+    }
+}
+
+// Data hash of the code script runs, the key of the native builds.
+fn code_hash(context: &Context, script: &Script) -> Option<Byte32> {
+    if script.hash_type() != ScriptHashType::Type.into() {
+        return Some(script.code_hash());
+    }
+    let out_point = context.cells_by_type_hash.get(&script.code_hash())?;
+    context.cells.get(out_point).map(|(_, data)| CellOutput::calc_data_hash(data))
+}
+
+// Codes of the type groups of tx the simulators get wrong: groups without
+// inputs, and groups whose outputs aren't outputs 0, 1, ...
+fn unsupported_codes(context: &Context, tx: &TransactionView) -> HashSet<Byte32> {
+    let mut groups: HashMap<Byte32, (Script, usize, Vec<usize>)> = HashMap::new();
+    for input in tx.inputs() {
+        let type_ = context
+            .cells
+            .get(&input.previous_output())
+            .and_then(|(output, _)| output.type_().to_opt());
+        if let Some(type_) = type_ {
+            groups.entry(type_.calc_script_hash()).or_insert_with(|| (type_, 0, Vec::new())).1 += 1;
+        }
+    }
+    for (i, output) in tx.outputs().into_iter().enumerate() {
+        if let Some(type_) = output.type_().to_opt() {
+            groups.entry(type_.calc_script_hash()).or_insert_with(|| (type_, 0, Vec::new())).2.push(i);
+        }
+    }
+    groups
+        .into_values()
+        .filter(|(_, inputs, outputs)| *inputs == 0 || outputs.iter().enumerate().any(|(i, index)| i != *index))
+        .filter_map(|(script, _, _)| code_hash(context, &script))
+        .collect()
+}
+
+// A copy of context running the native builds tx can use.
+fn simulator_context(context: &Context, tx: &TransactionView) -> Context {
+    let mut sim_context = Context::default();
+    sim_context.cells = context.cells.clone();
+    sim_context.transaction_infos = context.transaction_infos.clone();
+    sim_context.headers = context.headers.clone();
+    sim_context.epoches = context.epoches.clone();
+    sim_context.block_extensions = context.block_extensions.clone();
+    sim_context.cells_by_data_hash = context.cells_by_data_hash.clone();
+    sim_context.cells_by_type_hash = context.cells_by_type_hash.clone();
+    // ckb-testtool looks up the code cell of every type hash script and
+    // panics on the built-in Type ID, which has none. An empty cell without a
+    // native build hands it to the RISC-V verifier, which knows Type ID.
+    let type_id: Byte32 = TYPE_ID_REF.code_hash.pack();
+    if !sim_context.cells_by_type_hash.contains_key(&type_id) {
+        let out_point = OutPoint::new(type_id.clone(), u32::MAX);
+        sim_context
+            .cells
+            .insert(out_point.clone(), (CellOutput::default(), Bytes::new()));
+        sim_context.cells_by_type_hash.insert(type_id, out_point);
+    }
+    let unsupported = unsupported_codes(&sim_context, tx);
+    let simulators = SIMULATORS.lock().unwrap_or_else(|err| err.into_inner());
+    for (code_hash, path) in simulators.iter() {
+        if !unsupported.contains(code_hash) {
+            sim_context.set_simulator(code_hash.clone(), path.to_str().expect("utf8 path"));
+        }
+    }
+    sim_context
+}
+
+pub fn verify_tx(context: &Context, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error> {
+    // ckb-testtool hands the transaction to native scripts through a shared
+    // file and environment variables, one verification at a time
+    static VERIFY: Mutex<()> = Mutex::new(());
+    let _guard = VERIFY.lock().unwrap_or_else(|err| err.into_inner());
+    let sim_context = simulator_context(context, tx);
+    env::remove_var(EXIT_CODE_VAR);
+    let result = sim_context.verify_tx(tx, max_cycles);
+    let failure = env::var(EXIT_CODE_VAR).ok();
+    env::remove_var(EXIT_CODE_VAR);
+    match (failure, result) {
+        (Some(failure), Ok(_)) => {
+            let (name, exit_code) = failure.rsplit_once(' ').expect("name and exit code");
+            let exit_code = exit_code.parse().expect("exit code");
+            Err(ScriptError::ValidationFailure(name.to_string(), exit_code)
+                .unknown_source()
+                .into())
+        }
+        (_, result) => result,
+    }
+}
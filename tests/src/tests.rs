@@ -1,167 +1,889 @@
-use crate::Loader;
+use crate::builder::*;
+use crate::{deploy_contract, verify_tx};
 use ckb_testtool::ckb_types::{
     bytes::Bytes,
-    core::TransactionBuilder,
+    core::{ScriptHashType, TransactionBuilder},
     packed::*,
     prelude::*,
 };
 use ckb_testtool::context::Context;
+use ckbfi_common::{
+    error::{decode, BONDINGS_CURVE, CONTRACTS, ORDER, UNIQUE_LIQUIDITY_MANAGER},
+    registry::REGISTRY_TYPE_HASH,
+    script_ref::SCRIPT_REF_LEN,
+};
+use ckbfi_curve::{
+    creator_buy_cost, quote_buy, xudt_for_ckb, LAUNCH_CKB_AMOUNT, LAUNCH_XUDT_AMOUNT, MAX_CREATOR_BUY,
+    TOTAL_XUDT_SUPPLY, XUDT_UNIT,
+};
 
 // Include your tests here
 // See https://github.com/xxuejie/ckb-native-build-sample/blob/main/tests/src/tests.rs for more examples
 
-// generated unit test for contract order-contract
-#[test]
-fn test_order_contract() {
-    // deploy contract
-    let mut context = Context::default();
-    let contract_bin: Bytes = Loader::default().load_binary("order-contract");
-    let out_point = context.deploy_cell(contract_bin);
-
-    // prepare scripts
-    let lock_script = context
-        .build_script(&out_point, Bytes::from(vec![42]))
-        .expect("script");
-
-    // prepare cells
-    let input_out_point = context.create_cell(
-        CellOutput::new_builder()
-            .capacity(1000u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        Bytes::new(),
+const POOL_XUDT: u128 = 500_000_000 * 100_000_000;
+// ckb the reserve starts with in the pool genesis transaction
+const GENESIS_CKB: u64 = 10_000 * 100_000_000;
+const BUY_AMOUNT: u128 = 10_000_000 * XUDT_UNIT;
+const SELL_AMOUNT: u128 = 4_000_000 * XUDT_UNIT;
+// 1%
+const SLIP_POINT: u16 = 100;
+
+// bondings-curve-contract errors
+const ERROR_OUTPUT_VALIDATION_FAILURE: i8 = BONDINGS_CURVE.code("OutPutValidationFailure").unwrap();
+const ERROR_USER_PAY_CKB_NOT_ENOUGH: i8 = BONDINGS_CURVE.code("UserPayCkbNotEnough").unwrap();
+const ERROR_USER_PAY_XUDT_NOT_ENOUGH: i8 = BONDINGS_CURVE.code("UserPayXudtNotEnough").unwrap();
+const ERROR_PRICE_OUT_OF_RANGE: i8 = BONDINGS_CURVE.code("PriceOutOfRange").unwrap();
+const ERROR_USER_RECEIVE_XUDT_NOT_ENOUGH: i8 = BONDINGS_CURVE.code("UserReceiveXudtNotEnough").unwrap();
+const ERROR_POOL_NOT_ACTIVE: i8 = BONDINGS_CURVE.code("PoolNotActive").unwrap();
+const ERROR_CURVE_REGISTRY_MISSING: i8 = BONDINGS_CURVE.code("RegistryMissing").unwrap();
+
+// unique_liquidity_manager errors
+const ERROR_INVALID_LIQUIDITY: i8 = UNIQUE_LIQUIDITY_MANAGER.code("InvalidLiquidity").unwrap();
+const ERROR_INVALID_GOVERNANCE: i8 = UNIQUE_LIQUIDITY_MANAGER.code("InvalidGovernance").unwrap();
+const ERROR_REGISTRY_MISMATCH: i8 = UNIQUE_LIQUIDITY_MANAGER.code("RegistryMismatch").unwrap();
+const ERROR_INVALID_GENESIS: i8 = UNIQUE_LIQUIDITY_MANAGER.code("InvalidGenesis").unwrap();
+const ERROR_ISSUANCE_NOT_CLOSED: i8 = UNIQUE_LIQUIDITY_MANAGER.code("IssuanceNotClosed").unwrap();
+const ERROR_INVALID_CREATOR_BUY: i8 = UNIQUE_LIQUIDITY_MANAGER.code("InvalidCreatorBuy").unwrap();
+const ERROR_INVALID_METADATA: i8 = UNIQUE_LIQUIDITY_MANAGER.code("InvalidMetadata").unwrap();
+const ERROR_METADATA_IMMUTABLE: i8 = UNIQUE_LIQUIDITY_MANAGER.code("MetadataImmutable").unwrap();
+const ERROR_MANAGER_REGISTRY_MISSING: i8 = UNIQUE_LIQUIDITY_MANAGER.code("RegistryMissing").unwrap();
+
+// order-contract errors
+const ERROR_OUTPUT_INVALID: i8 = ORDER.code("OutputInvalid").unwrap();
+const ERROR_MISS_MATCH_BONDINGS_CELL: i8 = ORDER.code("MissMatchBondingsCell").unwrap();
+const ERROR_USER_PAY_CKB_TOO_MUCH: i8 = ORDER.code("UserPayCkbTooMuch").unwrap();
+const ERROR_MULTIPLE_ORDER_GROUPS: i8 = ORDER.code("MultipleOrderGroups").unwrap();
+
+fn assert_script_error(err: ckb_testtool::ckb_error::Error, err_code: i8) {
+    let error_string = err.to_string();
+    assert!(
+        error_string.contains(format!("error code {} ", err_code).as_str()),
+        "error_string: {}, expected_error_code: {} ({:?})",
+        error_string,
+        err_code,
+        decode(err_code)
     );
-    let input = CellInput::new_builder()
-        .previous_output(input_out_point)
-        .build();
+}
+
+// The Error enums of the contracts match the decoding table in
+// ckbfi_common::error variant by variant.
+#[test]
+fn test_error_codes_match_contracts() {
+    for codes in CONTRACTS {
+        let path = format!("{}/../contracts/{}/src/error.rs", env!("CARGO_MANIFEST_DIR"), codes.contract);
+        let source = std::fs::read_to_string(&path).expect("error.rs");
+        let body = source
+            .split_once("pub enum Error {")
+            .and_then(|(_, rest)| rest.split_once('}'))
+            .expect("Error enum")
+            .0;
+        let variants: Vec<_> = body
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("//"))
+            .map(|line| line.split(['=', ',']).next().unwrap().trim())
+            .collect();
+        assert_eq!(variants, codes.names, "{}", codes.contract);
+    }
+    assert_eq!(decode(ERROR_OUTPUT_VALIDATION_FAILURE).unwrap().name, "OutPutValidationFailure");
+    assert_eq!(decode(ERROR_OUTPUT_INVALID).unwrap().contract, "order-contract");
+    assert_eq!(decode(ERROR_INVALID_LIQUIDITY).unwrap().contract, "unique_liquidity_manager");
+    assert_eq!(decode(5), None);
+    assert_eq!(decode(UNIQUE_LIQUIDITY_MANAGER.next()), None);
+}
+
+// A pool of token A whose unique cell is only transferred. The curve lock and
+// both tokens are always success scripts, so only the liquidity manager is
+// checked.
+struct LiquidityFixture {
+    context: Context,
+    owner_lock: Script,
+    unique_type: Script,
+    token_a: Script,
+    // curve lock of the token A pool
+    curve_lock: Script,
+    // curve lock with args token B | unique cell type hash
+    token_b_curve_lock: Script,
+    inputs: Vec<CellInput>,
+}
+
+impl LiquidityFixture {
+    fn new() -> Self {
+        let mut context = Context::default();
+        let manager_out_point = deploy_contract(&mut context, "unique_liquidity_manager");
+        let always_success = context.deploy_cell(ckb_testtool::builtin::ALWAYS_SUCCESS.clone());
+
+        let owner_lock = context.build_script(&always_success, Bytes::new()).expect("script");
+        let token_a = context.build_script(&always_success, Bytes::from(vec![0xa])).expect("script");
+        let token_b = context.build_script(&always_success, Bytes::from(vec![0xb])).expect("script");
+
+        let mut unique_args = token_bytes(TOKEN_STANDARD_CUSTOM, &token_a);
+        unique_args.extend_from_slice(&[1u8; 32]);
+        let unique_type = context
+            .build_script(&manager_out_point, unique_args.into())
+            .expect("script");
+        let unique_hash = unique_type.calc_script_hash();
+
+        let mut args = token_bytes(TOKEN_STANDARD_CUSTOM, &token_a);
+        args.extend_from_slice(unique_hash.as_slice());
+        let curve_lock = context.build_script(&always_success, args.into()).expect("script");
+        let mut args = token_bytes(TOKEN_STANDARD_CUSTOM, &token_b);
+        args.extend_from_slice(unique_hash.as_slice());
+        let token_b_curve_lock = context.build_script(&always_success, args.into()).expect("script");
+
+        let cells = vec![
+            (
+                cell(&owner_lock, Some(&unique_type), CELL_CAPACITY),
+                pool_data(POOL_XUDT, CELL_CAPACITY, &curve_lock, false),
+            ),
+            (
+                cell(&curve_lock, Some(&token_a), CELL_CAPACITY),
+                Bytes::from(POOL_XUDT.to_le_bytes().to_vec()),
+            ),
+            (cell(&curve_lock, None, CELL_CAPACITY), Bytes::new()),
+        ];
+        let inputs = cells
+            .into_iter()
+            .map(|(output, data)| {
+                CellInput::new_builder()
+                    .previous_output(context.create_cell(output, data))
+                    .build()
+            })
+            .collect();
+        LiquidityFixture {
+            context,
+            owner_lock,
+            unique_type,
+            token_a,
+            curve_lock,
+            token_b_curve_lock,
+            inputs,
+        }
+    }
+
+    fn verify(&mut self, outputs: Vec<(CellOutput, Bytes)>) -> Result<u64, ckb_testtool::ckb_error::Error> {
+        let (outputs, outputs_data): (Vec<_>, Vec<_>) = outputs.into_iter().unzip();
+        let tx = TransactionBuilder::default()
+            .inputs(self.inputs.clone())
+            .outputs(outputs)
+            .outputs_data(outputs_data.pack())
+            .build();
+        let tx = self.context.complete_tx(tx);
+        verify_tx(&self.context, &tx, MAX_CYCLES)
+    }
+}
+
+#[test]
+fn test_unique_liquidity_manager_transfer_pool() {
+    let mut pool = LiquidityFixture::new();
+    let outputs = vec![
+        (
+            cell(&pool.owner_lock, Some(&pool.unique_type), CELL_CAPACITY),
+            pool_data(POOL_XUDT, CELL_CAPACITY, &pool.curve_lock, false),
+        ),
+        (
+            cell(&pool.curve_lock, Some(&pool.token_a), CELL_CAPACITY),
+            Bytes::from(POOL_XUDT.to_le_bytes().to_vec()),
+        ),
+        (cell(&pool.curve_lock, None, CELL_CAPACITY), Bytes::new()),
+    ];
+    pool.verify(outputs).expect("pass verification");
+}
+
+// ckb locked for token B's pool args can't back the token A pool
+#[test]
+fn test_unique_liquidity_manager_ckb_of_other_token() {
+    let mut pool = LiquidityFixture::new();
+    let moved = CELL_CAPACITY / 2;
     let outputs = vec![
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script)
-            .build(),
+        (
+            cell(&pool.owner_lock, Some(&pool.unique_type), CELL_CAPACITY),
+            pool_data(POOL_XUDT, CELL_CAPACITY, &pool.curve_lock, false),
+        ),
+        (
+            cell(&pool.curve_lock, Some(&pool.token_a), CELL_CAPACITY),
+            Bytes::from(POOL_XUDT.to_le_bytes().to_vec()),
+        ),
+        (cell(&pool.curve_lock, None, CELL_CAPACITY - moved), Bytes::new()),
+        (cell(&pool.token_b_curve_lock, None, moved), Bytes::new()),
     ];
+    let err = pool.verify(outputs).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_LIQUIDITY);
+}
 
-    let outputs_data = vec![Bytes::new(); 2];
+// token A locked for token B's pool args can't back the token A pool
+#[test]
+fn test_unique_liquidity_manager_xudt_of_other_token() {
+    let mut pool = LiquidityFixture::new();
+    let moved = POOL_XUDT / 2;
+    let outputs = vec![
+        (
+            cell(&pool.owner_lock, Some(&pool.unique_type), CELL_CAPACITY),
+            pool_data(POOL_XUDT, CELL_CAPACITY, &pool.curve_lock, false),
+        ),
+        (
+            cell(&pool.curve_lock, Some(&pool.token_a), CELL_CAPACITY / 2),
+            Bytes::from((POOL_XUDT - moved).to_le_bytes().to_vec()),
+        ),
+        (
+            cell(&pool.token_b_curve_lock, Some(&pool.token_a), CELL_CAPACITY / 2),
+            Bytes::from(moved.to_le_bytes().to_vec()),
+        ),
+        (cell(&pool.curve_lock, None, CELL_CAPACITY), Bytes::new()),
+    ];
+    let err = pool.verify(outputs).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_LIQUIDITY);
+}
 
-    // build transaction
-    let tx = TransactionBuilder::default()
-        .input(input)
-        .outputs(outputs)
-        .outputs_data(outputs_data.pack())
-        .build();
-    let tx = context.complete_tx(tx);
-
-    // run
-    let cycles = context
-        .verify_tx(&tx, 10_000_000)
-        .expect("pass verification");
-    println!("consume cycles: {}", cycles);
-}
-
-// generated unit test for contract bondings-curve-contract
-#[test]
-fn test_bondings_curve_contract() {
-    // deploy contract
-    let mut context = Context::default();
-    let contract_bin: Bytes = Loader::default().load_binary("bondings-curve-contract");
-    let out_point = context.deploy_cell(contract_bin);
-
-    // prepare scripts
-    let lock_script = context
-        .build_script(&out_point, Bytes::from(vec![42]))
-        .expect("script");
-
-    // prepare cells
-    let input_out_point = context.create_cell(
-        CellOutput::new_builder()
-            .capacity(1000u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        Bytes::new(),
-    );
-    let input = CellInput::new_builder()
-        .previous_output(input_out_point)
-        .build();
+// reserves moved out of the pool (e.g. launch), the unique cell records it
+#[test]
+fn test_unique_liquidity_manager_reserves_emptied() {
+    let mut pool = LiquidityFixture::new();
     let outputs = vec![
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script)
-            .build(),
+        (
+            cell(&pool.owner_lock, Some(&pool.unique_type), CELL_CAPACITY),
+            pool_data(0, 0, &pool.curve_lock, false),
+        ),
+        (
+            cell(&pool.owner_lock, Some(&pool.token_a), CELL_CAPACITY),
+            Bytes::from(POOL_XUDT.to_le_bytes().to_vec()),
+        ),
+        (cell(&pool.owner_lock, None, CELL_CAPACITY), Bytes::new()),
     ];
+    pool.verify(outputs).expect("pass verification");
+}
 
-    let outputs_data = vec![Bytes::new(); 2];
+#[test]
+fn test_unique_liquidity_manager_reserves_emptied_stale_data() {
+    let mut pool = LiquidityFixture::new();
+    let outputs = vec![
+        (
+            cell(&pool.owner_lock, Some(&pool.unique_type), CELL_CAPACITY),
+            pool_data(POOL_XUDT, CELL_CAPACITY, &pool.curve_lock, false),
+        ),
+        (
+            cell(&pool.owner_lock, Some(&pool.token_a), CELL_CAPACITY),
+            Bytes::from(POOL_XUDT.to_le_bytes().to_vec()),
+        ),
+        (cell(&pool.owner_lock, None, CELL_CAPACITY), Bytes::new()),
+    ];
+    let err = pool.verify(outputs).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_LIQUIDITY);
+}
 
-    // build transaction
-    let tx = TransactionBuilder::default()
-        .input(input)
-        .outputs(outputs)
-        .outputs_data(outputs_data.pack())
-        .build();
-    let tx = context.complete_tx(tx);
-
-    // run
-    let cycles = context
-        .verify_tx(&tx, 10_000_000)
-        .expect("pass verification");
-    println!("consume cycles: {}", cycles);
-}
-
-// generated unit test for contract unique_liquidity_manager
-#[test]
-fn test_unique_liquidity_manager() {
-    // deploy contract
-    let mut context = Context::default();
-    let contract_bin: Bytes = Loader::default().load_binary("unique_liquidity_manager");
-    let out_point = context.deploy_cell(contract_bin);
-
-    // prepare scripts
-    let lock_script = context
-        .build_script(&out_point, Bytes::from(vec![42]))
-        .expect("script");
-
-    // prepare cells
-    let input_out_point = context.create_cell(
-        CellOutput::new_builder()
-            .capacity(1000u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        Bytes::new(),
-    );
-    let input = CellInput::new_builder()
-        .previous_output(input_out_point)
-        .build();
+#[test]
+fn test_unique_liquidity_manager_ckb_reserve_emptied_stale_data() {
+    let mut pool = LiquidityFixture::new();
     let outputs = vec![
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script)
-            .build(),
+        (
+            cell(&pool.owner_lock, Some(&pool.unique_type), CELL_CAPACITY),
+            pool_data(POOL_XUDT, CELL_CAPACITY, &pool.curve_lock, false),
+        ),
+        (
+            cell(&pool.curve_lock, Some(&pool.token_a), CELL_CAPACITY),
+            Bytes::from(POOL_XUDT.to_le_bytes().to_vec()),
+        ),
+        (cell(&pool.owner_lock, None, CELL_CAPACITY), Bytes::new()),
     ];
+    let err = pool.verify(outputs).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_LIQUIDITY);
+}
 
-    let outputs_data = vec![Bytes::new(); 2];
+#[test]
+fn test_unique_liquidity_manager_burn_with_reserves() {
+    let mut pool = LiquidityFixture::new();
+    let outputs = vec![
+        (
+            cell(&pool.curve_lock, Some(&pool.token_a), CELL_CAPACITY),
+            Bytes::from(POOL_XUDT.to_le_bytes().to_vec()),
+        ),
+        (cell(&pool.curve_lock, None, CELL_CAPACITY), Bytes::new()),
+    ];
+    let err = pool.verify(outputs).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_LIQUIDITY);
+}
 
-    // build transaction
-    let tx = TransactionBuilder::default()
-        .input(input)
-        .outputs(outputs)
-        .outputs_data(outputs_data.pack())
+#[test]
+fn test_unique_liquidity_manager_burn_after_reserves_emptied() {
+    let mut pool = LiquidityFixture::new();
+    let outputs = vec![
+        (
+            cell(&pool.owner_lock, Some(&pool.token_a), CELL_CAPACITY),
+            Bytes::from(POOL_XUDT.to_le_bytes().to_vec()),
+        ),
+        (cell(&pool.owner_lock, None, CELL_CAPACITY), Bytes::new()),
+    ];
+    pool.verify(outputs).expect("pass verification");
+}
+
+
+// An xUDT pool after genesis, trades start from a full reserve.
+fn live_pool() -> PoolFixture {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    pool
+}
+
+#[test]
+fn test_create_pool() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    pool.verify(&tx).expect("pass verification");
+}
+
+#[test]
+fn test_create_pool_with_creator_buy() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let creator_buy = 1_000_000 * XUDT_UNIT;
+    let cost = creator_buy_cost(creator_buy, 0).unwrap() as u64;
+    let tx = pool.create_pool(creator_buy, GENESIS_CKB + cost);
+    pool.verify(&tx).expect("pass verification");
+}
+
+#[test]
+fn test_create_pool_creator_buy_underpaid() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let creator_buy = 1_000_000 * XUDT_UNIT;
+    let cost = creator_buy_cost(creator_buy, 0).unwrap() as u64;
+    let tx = pool.create_pool(creator_buy, cost - 1);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_CREATOR_BUY);
+}
+
+#[test]
+fn test_create_pool_creator_buy_over_limit() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let creator_buy = MAX_CREATOR_BUY + XUDT_UNIT;
+    let cost = quote_buy(TOTAL_XUDT_SUPPLY, creator_buy).unwrap() as u64;
+    let tx = pool.create_pool(creator_buy, GENESIS_CKB + cost);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_CREATOR_BUY);
+}
+
+// one token of the supply is missing from the outputs
+#[test]
+fn test_create_pool_partial_supply() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let tx = remove_output(&pool.create_pool(XUDT_UNIT, GENESIS_CKB), 4);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_GENESIS);
+}
+
+#[test]
+fn test_create_pool_without_metadata() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let tx = remove_output(&pool.create_pool(0, GENESIS_CKB), 3);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_GENESIS);
+}
+
+#[test]
+fn test_create_xudt_pool() {
+    let mut pool = PoolFixture::with_closed_issuance();
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    pool.verify(&tx).expect("pass verification");
+}
+
+// the issuer keeps the owner cell and can mint later
+#[test]
+fn test_create_xudt_pool_owner_kept() {
+    let mut pool = PoolFixture::with_closed_issuance();
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    let owner_type = type_id_script(&ISSUANCE_OWNER_ARGS);
+    let tx = tx
+        .as_advanced_builder()
+        .output(cell(&pool.issuer_lock, Some(&owner_type), CELL_CAPACITY))
+        .output_data(Bytes::new().pack())
         .build();
-    let tx = context.complete_tx(tx);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_ISSUANCE_NOT_CLOSED);
+}
+
+// xUDT owned by the issuer lock, nothing proves the issuance closed
+#[test]
+fn test_create_xudt_pool_issuance_open() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_ISSUANCE_NOT_CLOSED);
+}
+
+// arweave_tx_id is set after genesis
+#[test]
+fn test_create_pool_with_arweave_tx_id() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    let metadata = tx.output(3).unwrap();
+    let tx = replace_output(&tx, 3, metadata, metadata_data(&[7u8; 32]));
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_METADATA);
+}
+
+// the metadata cell of a new pool, live
+fn live_metadata(pool: &mut PoolFixture) -> CellInput {
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    pool.commit(&tx)[3].clone()
+}
+
+#[test]
+fn test_set_arweave_tx_id() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let metadata = live_metadata(&mut pool);
+    let tx = pool.set_arweave_tx_id(&metadata, &[7u8; 32]);
+    pool.verify(&tx).expect("pass verification");
+}
+
+#[test]
+fn test_set_arweave_tx_id_twice() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let metadata = live_metadata(&mut pool);
+    let tx = pool.set_arweave_tx_id(&metadata, &[7u8; 32]);
+    let metadata = pool.commit(&tx)[0].clone();
+    let tx = pool.set_arweave_tx_id(&metadata, &[8u8; 32]);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_METADATA_IMMUTABLE);
+}
+
+// only the creator ever sets arweave_tx_id, the metadata cell can't change hands
+#[test]
+fn test_set_arweave_tx_id_moves_metadata() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let metadata = live_metadata(&mut pool);
+    let tx = pool.set_arweave_tx_id(&metadata, &[7u8; 32]);
+    let user = pool.user("user");
+    let output = cell(&user, Some(&pool.metadata_type), CELL_CAPACITY);
+    let tx = replace_output(&tx, 0, output, metadata_data(&[7u8; 32]));
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_METADATA_IMMUTABLE);
+}
+
+#[test]
+fn test_registry_type_hash() {
+    assert_eq!(registry_type().calc_script_hash().as_slice(), REGISTRY_TYPE_HASH);
+}
+
+// registry entries
+const REGISTRY_XUDT: usize = 0;
+const REGISTRY_UTXOSWAP: usize = 1;
+const REGISTRY_BONDINGS_CURVE: usize = 3;
+const REGISTRY_UNIQUE_LIQUIDITY_MANAGER: usize = 4;
+
+// replaces the registry by one with entry `index` pointing at the code of script
+fn set_registry_entry(pool: &mut PoolFixture, index: usize, script: &Script) {
+    let mut data = pool.deployment.registry_data().to_vec();
+    data[index * SCRIPT_REF_LEN..(index + 1) * SCRIPT_REF_LEN].copy_from_slice(&script_ref(script));
+    pool.deployment.set_registry(Some(data.into()));
+}
+
+// replaces the registry by one with entry `index` pointing at code nothing uses
+fn set_unknown_registry_entry(pool: &mut PoolFixture, index: usize) {
+    let unknown = Script::new_builder()
+        .code_hash([7u8; 32].pack())
+        .hash_type(ScriptHashType::Type.into())
+        .build();
+    set_registry_entry(pool, index, &unknown);
+}
+
+// the bonding curve pinned to its data hash instead of referenced by its Type ID
+fn data_hash_curve(pool: &mut PoolFixture) -> Script {
+    let deployment = &mut pool.deployment;
+    deployment
+        .context
+        .build_script_with_hash_type(&deployment.curve_out_point, ScriptHashType::Data1, Bytes::new())
+        .expect("script")
+}
+
+#[test]
+fn test_create_pool_without_registry() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    pool.deployment.set_registry(None);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_MANAGER_REGISTRY_MISSING);
+}
+
+// the pool is locked with a curve the registry doesn't list
+#[test]
+fn test_create_pool_unregistered_curve() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    set_unknown_registry_entry(&mut pool, REGISTRY_BONDINGS_CURVE);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_REGISTRY_MISMATCH);
+}
+
+#[test]
+fn test_create_pool_unregistered_manager() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    set_unknown_registry_entry(&mut pool, REGISTRY_UNIQUE_LIQUIDITY_MANAGER);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_REGISTRY_MISMATCH);
+}
+
+#[test]
+fn test_create_xudt_pool_unregistered_deployment() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    set_unknown_registry_entry(&mut pool, REGISTRY_XUDT);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_REGISTRY_MISMATCH);
+}
+
+// declaring an xUDT token custom would skip the issuance proof
+#[test]
+fn test_create_pool_xudt_declared_custom() {
+    let mut pool = PoolFixture::with_xudt_declared_as(TOKEN_STANDARD_CUSTOM);
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_REGISTRY_MISMATCH);
+}
+
+#[test]
+fn test_buy() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    pool.verify(&tx).expect("pass verification");
+}
+
+#[test]
+fn test_buy_ckb_not_enough() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let cost = pool.buy_cost(BUY_AMOUNT);
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    let tx = pool.set_reserves(&tx, TOTAL_XUDT_SUPPLY - BUY_AMOUNT, GENESIS_CKB + cost - 1);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_USER_PAY_CKB_NOT_ENOUGH);
+}
+
+// the ckb paid buys one more token than the user receives
+#[test]
+fn test_buy_overpaid() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let cost = pool.buy_cost(BUY_AMOUNT + XUDT_UNIT);
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    let tx = pool.set_reserves(&tx, TOTAL_XUDT_SUPPLY - BUY_AMOUNT, GENESIS_CKB + cost);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_USER_RECEIVE_XUDT_NOT_ENOUGH);
+}
+
+#[test]
+fn test_buy_below_launch_reserve() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    pool.open(LAUNCH_XUDT_AMOUNT + XUDT_UNIT, GENESIS_CKB);
+    let user = pool.user("user");
+    let tx = pool.buy(&user, 2 * XUDT_UNIT, SLIP_POINT);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_OUTPUT_VALIDATION_FAILURE);
+}
+
+// the user receives less than desired_amount after slippage
+#[test]
+fn test_buy_order_slippage_exceeded() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let order = pool.buy_order(&user, BUY_AMOUNT, SLIP_POINT);
+    let tx = pool.fill_buy(&order, BUY_AMOUNT / 100 * 98);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_OUTPUT_INVALID);
+}
+
+// the orders would count the same user output as their fill
+#[test]
+fn test_buy_orders_of_one_user_with_different_args() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let first = pool.buy_order(&user, BUY_AMOUNT, SLIP_POINT);
+    let second = pool.buy_order(&user, SELL_AMOUNT, SLIP_POINT);
+    let tx = pool.fill_buys(&[(&first, BUY_AMOUNT), (&second, SELL_AMOUNT)]);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_MULTIPLE_ORDER_GROUPS);
+}
+
+// orders with the same args are one script group, each one needs its fill
+#[test]
+fn test_buy_orders_of_one_user_with_same_args() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let first = pool.buy_order(&user, BUY_AMOUNT, SLIP_POINT);
+    let second = pool.buy_order(&user, BUY_AMOUNT, SLIP_POINT);
+    let tx = pool.fill_buys(&[(&first, BUY_AMOUNT), (&second, BUY_AMOUNT)]);
+    pool.verify(&tx).expect("pass verification");
+    let tx = pool.fill_buys(&[(&first, BUY_AMOUNT), (&second, BUY_AMOUNT / 2)]);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_OUTPUT_INVALID);
+}
+
+#[test]
+fn test_buy_stale_pool_data() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    let (unique, data) = pool.unique_cell(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let tx = replace_output(&tx, 2, unique, data);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_LIQUIDITY);
+}
+
+// the change of ckb_in goes back to the user with the xudt cell
+#[test]
+fn test_buy_exact_in() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let tx = pool.buy_exact_in(&user, 500 * 100_000_000, SLIP_POINT);
+    pool.verify(&tx).expect("pass verification");
+}
+
+#[test]
+fn test_buy_exact_in_spends_more_than_ckb_in() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let ckb_in = 500 * 100_000_000;
+    let bought = xudt_for_ckb(TOTAL_XUDT_SUPPLY, ckb_in as u128).unwrap();
+    let tx = pool.buy_exact_in(&user, ckb_in, SLIP_POINT);
+    let output = cell(&user, Some(&pool.token_type), CELL_CAPACITY - 100_000_000);
+    let tx = replace_output(&tx, 3, output, udt_data(bought));
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_USER_PAY_CKB_TOO_MUCH);
+}
+
+#[test]
+fn test_buy_with_pool_fee() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_ACTIVE, 100);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let user = pool.user("user");
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    pool.verify(&tx).expect("pass verification");
+}
+
+#[test]
+fn test_buy_without_pool_fee() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_ACTIVE, 100);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let user = pool.user("user");
+    let cost = quote_buy(TOTAL_XUDT_SUPPLY, BUY_AMOUNT).unwrap() as u64;
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    let tx = pool.set_reserves(&tx, TOTAL_XUDT_SUPPLY - BUY_AMOUNT, GENESIS_CKB + cost);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_USER_PAY_CKB_NOT_ENOUGH);
+}
+
+#[test]
+fn test_buy_paused_pool() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_PAUSED, 0);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let user = pool.user("user");
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_POOL_NOT_ACTIVE);
+}
+
+// the admin withdraws everything to UTXOSwap when the pool can't launch
+#[test]
+fn test_emergency_graduation() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_ACTIVE, 0);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let tx = pool.emergency_graduate();
+    pool.verify(&tx).expect("pass verification");
+}
+
+// only the registry tells which outputs are UTXOSwap
+#[test]
+fn test_emergency_graduation_without_registry() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_ACTIVE, 0);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    pool.deployment.set_registry(None);
+    let tx = pool.emergency_graduate();
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_CURVE_REGISTRY_MISSING);
+}
+
+// launch outputs to a lock the registry doesn't list as UTXOSwap
+#[test]
+fn test_graduate_unregistered_utxoswap() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    pool.open(LAUNCH_XUDT_AMOUNT, LAUNCH_CKB_AMOUNT);
+    set_unknown_registry_entry(&mut pool, REGISTRY_UTXOSWAP);
+    let tx = pool.graduate();
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_OUTPUT_VALIDATION_FAILURE);
+}
+
+// graduated pools can't trade, the reserves would be locked for good
+#[test]
+fn test_graduate_without_reserves() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_PAUSED, 0);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let unique = pool.cells.last().unwrap().clone();
+    let admin = pool.admin_input();
+    pool.set_status(POOL_STATUS_GRADUATED);
+    let output = pool.unique_cell(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let tx = pool.deployment.build_tx(vec![unique, admin], vec![output]);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_GOVERNANCE);
+}
+
+// the pool moves its reserves to another reference of the curve
+#[test]
+fn test_migrate_to_registered_curve() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_ACTIVE, 0);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let curve = data_hash_curve(&mut pool);
+    set_registry_entry(&mut pool, REGISTRY_BONDINGS_CURVE, &curve);
+    let tx = pool.migrate(&curve);
+    pool.verify(&tx).expect("pass verification");
+}
+
+// migrations go through the same registry check as the pool genesis
+#[test]
+fn test_migrate_to_unregistered_curve() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_ACTIVE, 0);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let curve = data_hash_curve(&mut pool);
+    let tx = pool.migrate(&curve);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_REGISTRY_MISMATCH);
+}
+
+#[test]
+fn test_migrate_without_registry() {
+    let mut pool = PoolFixture::with_admin(TOKEN_STANDARD_XUDT, POOL_STATUS_ACTIVE, 0);
+    pool.open(TOTAL_XUDT_SUPPLY, GENESIS_CKB);
+    let curve = data_hash_curve(&mut pool);
+    pool.deployment.set_registry(None);
+    let tx = pool.migrate(&curve);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_MANAGER_REGISTRY_MISSING);
+}
+
+#[test]
+fn test_sell() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    pool.commit(&tx);
+    let tx = pool.sell(&user, SELL_AMOUNT);
+    pool.verify(&tx).expect("pass verification");
+}
+
+#[test]
+fn test_sell_ckb_too_much() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    pool.commit(&tx);
+    let proceeds = pool.sell_proceeds(SELL_AMOUNT) + 1;
+    let tx = pool.sell(&user, SELL_AMOUNT);
+    let tx = pool.set_reserves(&tx, pool.xudt_reserve + SELL_AMOUNT, pool.ckb_reserve - proceeds);
+    let tx = replace_output(&tx, 3, cell(&user, None, CELL_CAPACITY + proceeds), Bytes::new());
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_USER_PAY_XUDT_NOT_ENOUGH);
+}
+
+// the curve only prices whole tokens, a fraction would be traded for free
+#[test]
+fn test_buy_fractional_amount() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let cost = pool.buy_cost(BUY_AMOUNT + XUDT_UNIT);
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    let tx = pool.set_reserves(&tx, TOTAL_XUDT_SUPPLY - BUY_AMOUNT - 1, GENESIS_CKB + cost);
+    let output = cell(&user, Some(&pool.token_type), CELL_CAPACITY);
+    let tx = replace_output(&tx, 3, output, udt_data(BUY_AMOUNT + 1));
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_PRICE_OUT_OF_RANGE);
+}
+
+#[test]
+fn test_sell_fractional_amount() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    pool.commit(&tx);
+    let proceeds = pool.sell_proceeds(SELL_AMOUNT);
+    let order = pool.sell_order(&user, SELL_AMOUNT + 1);
+    let mut inputs = pool.cells.clone();
+    inputs.push(order.input.clone());
+    let mut outputs = pool.reserve_outputs(pool.xudt_reserve + SELL_AMOUNT + 1, pool.ckb_reserve - proceeds);
+    outputs.push((cell(&user, None, order.capacity + proceeds), Bytes::new()));
+    let tx = pool.deployment.build_tx(inputs, outputs);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_PRICE_OUT_OF_RANGE);
+}
+
+#[test]
+fn test_graduate() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    pool.open(LAUNCH_XUDT_AMOUNT, LAUNCH_CKB_AMOUNT);
+    let tx = pool.graduate();
+    pool.verify(&tx).expect("pass verification");
+}
+
+// without enough ckb for UTXOSwap it is a plain withdrawal from the pool
+#[test]
+fn test_graduate_ckb_not_enough() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_XUDT);
+    pool.open(LAUNCH_XUDT_AMOUNT, LAUNCH_CKB_AMOUNT);
+    let tx = pool.graduate();
+    let tx = replace_output(&tx, 1, cell(&utxoswap_lock(), None, LAUNCH_CKB_AMOUNT - 1), Bytes::new());
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_OUTPUT_VALIDATION_FAILURE);
+}
+
+#[test]
+fn test_cancel_order() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let order = pool.buy_order(&user, BUY_AMOUNT, SLIP_POINT);
+    let tx = pool.cancel(&order);
+    pool.verify(&tx).expect("pass verification");
+}
+
+// only the user or the pool named in the order can spend it
+#[test]
+fn test_take_order_without_pool() {
+    let mut pool = live_pool();
+    let user = pool.user("user");
+    let order = pool.buy_order(&user, BUY_AMOUNT, SLIP_POINT);
+    let aggregator_lock = pool.aggregator_lock.clone();
+    let aggregator_input = pool.deployment.input(cell(&aggregator_lock, None, CELL_CAPACITY), Bytes::new());
+    let output = (cell(&aggregator_lock, None, CELL_CAPACITY + order.capacity), Bytes::new());
+    let tx = pool
+        .deployment
+        .build_tx(vec![order.input.clone(), aggregator_input], vec![output]);
+    let err = pool.verify(&tx).unwrap_err();
+    assert_script_error(err, ERROR_MISS_MATCH_BONDINGS_CELL);
+}
+
+// Create a pool, buy, sell, buy the rest of the curve and launch on UTXOSwap.
+// Every transaction spends the pool cells created by the previous one.
+#[test]
+fn test_pool_lifecycle() {
+    let mut pool = PoolFixture::new(TOKEN_STANDARD_CUSTOM);
+    let user = pool.user("user");
+    let tx = pool.create_pool(0, GENESIS_CKB);
+    pool.verify(&tx).expect("pass verification");
+    pool.commit(&tx);
+
+    let tx = pool.buy(&user, BUY_AMOUNT, SLIP_POINT);
+    pool.verify(&tx).expect("pass verification");
+    pool.commit(&tx);
+
+    let tx = pool.sell(&user, SELL_AMOUNT);
+    pool.verify(&tx).expect("pass verification");
+    pool.commit(&tx);
+
+    let tx = pool.buy(&user, pool.xudt_reserve - LAUNCH_XUDT_AMOUNT, SLIP_POINT);
+    pool.verify(&tx).expect("pass verification");
+    pool.commit(&tx);
+    assert_eq!(pool.xudt_reserve, LAUNCH_XUDT_AMOUNT);
+    assert!(pool.ckb_reserve >= LAUNCH_CKB_AMOUNT);
 
-    // run
-    let cycles = context
-        .verify_tx(&tx, 10_000_000)
-        .expect("pass verification");
-    println!("consume cycles: {}", cycles);
+    let tx = pool.graduate();
+    pool.verify(&tx).expect("pass verification");
+    pool.commit(&tx);
+    assert_eq!((pool.xudt_reserve, pool.ckb_reserve), (0, 0));
 }